@@ -41,6 +41,7 @@ extern "C" fn test() -> u64 {
 
 unsafe fn start() -> ! {
     r0::zero_bss(&mut __bss_start, &mut __bss_end);
+    shared::sync::enable_exception_masking();
 
     let gpio = mmio::GPIO::new(memory::map::physical::GPIO_BASE);
     let mut v_mbox = mmio::Mbox::new(memory::map::physical::MBOX_BASE);
@@ -48,7 +49,7 @@ unsafe fn start() -> ! {
 
     match uart.init(&mut v_mbox, &gpio) {
         Ok(_) => {
-            mmio::LOGGER.appender(uart.into());
+            mmio::LOGGER.lock().appender(uart.into());
         }
         Err(_) => loop {
             panic!("uart not properly setup");
@@ -70,18 +71,26 @@ unsafe fn start() -> ! {
 
 /// Entrypoint of the processor.
 ///
-/// No need to park other processor it has been done by boot.c
+/// The other cores are usually parked by the firmware, when they are started here they wait
+/// in the same spin table for the kernel to release them.
 #[link_section = ".text.boot"]
 #[no_mangle]
 pub unsafe extern "C" fn _boot_cores() -> ! {
     const CORE_0: u64 = 0;
     const CORE_MASK: u64 = 0x3;
+    const SPIN_TABLE: u64 = 0xD8;
 
-    if CORE_0 == MPIDR_EL1.get() & CORE_MASK {
+    let core = MPIDR_EL1.get() & CORE_MASK;
+    if CORE_0 == core {
         SP.set(STACK_START);
         start();
     }
+    let release = (SPIN_TABLE + 8 * core) as *const u64;
     loop {
         asm::wfe();
+        let entry = release.read_volatile();
+        if entry != 0 {
+            asm!("br {}", in(reg) entry, options(noreturn));
+        }
     }
 }
\ No newline at end of file
@@ -11,14 +11,14 @@ pub mod map {
         pub const BOOT_START:          usize =             super::START;
         pub const BOOT_END:            usize =             0x0100_0000;
 
-        pub const KERN_START:          usize =             0x3900_0000;
-        pub const KERN_END:            usize =             0x3AFF_FFFF;
+        pub const INITRAMFS_START:     usize =             0x0080_0000;
+        pub const INITRAMFS_END:       usize =             0x00FF_FFFF;
 
-        pub const KERN_STACK_START:    usize =             0x3AF8_0000;
-        pub const KERN_STACK_END:      usize =             0x3AFF_FFFF;
+        pub const KERN_START:          usize =             0x0100_0000;
+        pub const KERN_END:            usize =             0x02FF_FFFF;
 
-        pub const GPU_BASE:            usize =             0x3B00_0000;
-        pub const GPU_END:             usize =             0x3EFF_FFFF;
+        pub const KERN_STACK_START:    usize =             0x02F8_0000;
+        pub const KERN_STACK_END:      usize =             0x02FF_FFFF;
 
         pub const MMIO_BASE:           usize =             0x3F00_0000;
         pub const IRQ_BASE:            usize = MMIO_BASE + 0x0000_B200;
@@ -56,11 +56,16 @@ unsafe fn reset() -> ! {
         Err(_err) => panic!("loading kernel failed"),
         _ => {}
     }
+    let initramfs_len = match load_initramfs(&mut uart) {
+        Err(_err) => panic!("loading initramfs failed"),
+        Ok(len) => len,
+    };
 
     debugln!("jump to upper level");
-    let upper_main: extern "C" fn() -> ! = core::mem::transmute(memory::map::virt::KERN_START);
+    // the kernel gets the physical address and the size of the initramfs
+    let upper_main: extern "C" fn(usize, usize) -> ! = core::mem::transmute(memory::map::virt::KERN_START);
     SP.set(memory::map::virt::KERN_STACK_START as u64);
-    upper_main()
+    upper_main(memory::map::physical::INITRAMFS_START, initramfs_len)
 }
 
 fn setup_mmu() -> Result<(), &'static str>{
@@ -72,16 +77,31 @@ fn setup_mmu() -> Result<(), &'static str>{
 unsafe fn load_kernel(uart: &mut Uart) -> IoResult<()> {
     debugln!("load kernel");
     uart.clear()?;
+    receive(uart, memory::map::physical::KERN_START, memory::map::physical::KERN_STACK_START)?;
+    Ok(())
+}
+
+/// The initramfs is sent right after the kernel, with a size of 0 when there is none.
+unsafe fn load_initramfs(uart: &mut Uart) -> IoResult<usize> {
+    receive(uart, memory::map::physical::INITRAMFS_START, memory::map::physical::INITRAMFS_END + 1)
+}
+
+/// Receive an image sent as its size followed by its bytes, and write it from `start`. The image
+/// must end before `end`.
+unsafe fn receive(uart: &mut Uart, start: usize, end: usize) -> IoResult<usize> {
     uart.writes("\x03\x03\x03")?;
     let len = uart.read_dword()?;
     uart.writes("\x03\x03\x03")?;
     let _ = uart.write_dword(len);
     uart.writes("\x03\x03\x03")?;
-    let kernel_addr: *mut u8 = memory::map::physical::KERN_START as *mut u8;
-    // Read the kernel byte by byte.
+    if start + len as usize > end {
+        panic!("image of {} bytes does not fit at {:#x}", len, start);
+    }
+    let addr: *mut u8 = start as *mut u8;
+    // Read the image byte by byte.
     for i in 0..len {
-        core::ptr::write_volatile(kernel_addr.offset(i as isize), uart.read_char()?);
+        core::ptr::write_volatile(addr.offset(i as isize), uart.read_char()?);
     }
     uart.writes("\x03\x03\x03")?;
-    Ok(())
+    Ok(len as usize)
 }
\ No newline at end of file
@@ -6,39 +6,77 @@
 #[macro_use] extern crate mmio;
 use aarch64_cpu::asm;
 use aarch64_cpu::registers::{CurrentEL, Readable};
-use mmio::syscall::SysCall;
-use qemu_exit::QEMUExit;
-
-extern "C" {
-    // Boundaries of the .bss section, provided by the linker script
-    static mut __bss_start: u64;
-    static mut __bss_end: u64;
-}
+use mmio::syscall::{SysCall, SIGTERM, MSG_MAX, NO_HANDLE};
 
 #[panic_handler]
 fn my_panic(info: &core::panic::PanicInfo) -> ! {
     println!("{:?}", info);
-    const QEMU_EXIT_HANDLE: qemu_exit::AArch64 = qemu_exit::AArch64::new();
-    QEMU_EXIT_HANDLE.exit_failure()
+    // only the program stops, with the exit code of the Rust panics
+    SysCall {}.exit(101)
 }
 
 /// Entrypoint of the init program
 #[link_section = ".text.start"]
 #[no_mangle]
 pub unsafe extern "C" fn _main() -> () {
-
-    r0::zero_bss(&mut __bss_start, &mut __bss_end);
-    mmio::SCREEN.appender(SysCall {}.into());
+    mmio::SCREEN.lock().appender(SysCall {}.into());
 
     println!("This is the init program, it is the first PID and will fork itself to create other programs");
 
     let sys_call = SysCall {};
+    let mut children = [0i64; 5];
+    for child in children.iter_mut() {
+        *child = sys_call.fork();
+        if *child == 0 {
+            sys_call.exec("/bin/program");
+            println!("init program could not exec program");
+            sys_call.exit(1);
+        }
+    }
+
+    // console server, the echo process prints what it receives through IPC calls to it
+    let console = sys_call.endpoint() as usize;
+    if sys_call.fork() == 0 {
+        let mut buf = [0u8; MSG_MAX];
+        loop {
+            let (len, sender, _) = sys_call.recv(console, &mut buf);
+            if len >= 0 {
+                println!("console received from {} : {}", sender, core::str::from_utf8(&buf[..len as usize]).unwrap_or("?"));
+                sys_call.reply(&[], NO_HANDLE);
+            }
+        }
+    }
+
+    // echo what is typed on the UART, this process only runs when something was received, 'p'
+    // lists the processes
+    if sys_call.fork() == 0 {
+        let mut buf = [0u8; 64];
+        loop {
+            let len = sys_call.read(&mut buf);
+            if len > 0 {
+                sys_call.call(console, &buf[..len as usize], &mut [], NO_HANDLE);
+            }
+            if buf[..len.max(0) as usize].contains(&b'p') {
+                println!("  PID  PPID  NICE  STATE       CPU TICKS  SWITCHES  MEMORY");
+                for info in userland::processes() {
+                    println!("{:5} {:5} {:5}  {:10} {:10} {:9} {:6}K", info.pid, info.parent, info.nice,
+                        info.state.name(), info.cpu_time, info.switches, info.memory / 1024);
+                }
+            }
+        }
+    }
+
     let mut count:u128 = 0;
     loop {
         if count % 100000000  == 0 {
             println!("init program run at level 0, count {}", count);
             sys_call.sleep(1000);
         }
+        if count == 300000000 {
+            sys_call.kill(children[0] as u16, SIGTERM);
+            let (pid, code) = sys_call.waitpid(children[0]);
+            println!("init program terminated {}, exit code {}", pid, code);
+        }
         count = count + 1;
     }
 
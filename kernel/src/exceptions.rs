@@ -1,5 +1,5 @@
 
-mod syscalls;
+pub(crate) mod syscalls;
 mod interruptions;
 
 use shared::exceptions::handlers::ExceptionContext;
@@ -7,6 +7,8 @@ use aarch64_cpu::registers::{ESR_EL1, FAR_EL1, SPSR_EL1, SP_EL0, CurrentEL, Read
 use qemu_exit::QEMUExit;
 use aarch64_cpu::asm::*;
 use crate::exceptions::interruptions::irq_handler;
+use crate::global::SCHEDULER;
+use mmio::syscall::{SIGILL, SIGSEGV};
 
 extern "C" {
     static __exception_vectors_start: u64;
@@ -27,7 +29,7 @@ unsafe extern "C" fn default_exception_handler(e: &ExceptionContext)  {
 }
 
 #[no_mangle]
-unsafe extern "C" fn current_elx_synchronous(e: &ExceptionContext) {
+unsafe extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
     if ESR_EL1.read(ESR_EL1::EC) == 0x15 { // SVC call
         syscalls::syscalls(e)
     } else {
@@ -36,11 +38,19 @@ unsafe extern "C" fn current_elx_synchronous(e: &ExceptionContext) {
 }
 
 #[no_mangle]
-unsafe extern "C" fn lower_aarch64_synchronous(e : &ExceptionContext) {
-    if ESR_EL1.read(ESR_EL1::EC) == 0x15 { // SVC call
+unsafe extern "C" fn lower_aarch64_synchronous(e : &mut ExceptionContext) {
+    let ec = ESR_EL1.read(ESR_EL1::EC);
+    if ec == 0x15 { // SVC call
         syscalls::syscalls(e)
+    } else if ec == 0x07 { // FP/SIMD access trapped
+        SCHEDULER.lock().fp_trap()
+    } else if ec == 0x20 || ec == 0x24 { // instruction or data abort
+        SCHEDULER.lock().page_fault(FAR_EL1.get() as usize, e)
+    } else if ec == 0x22 || ec == 0x26 { // PC or SP alignment fault
+        SCHEDULER.lock().fault(SIGSEGV, e)
     } else {
-        debug_halt("lower_aarch64_synchronous", e);
+        debugln!("illegal instruction at {:#x}, exception class {:#x}", e.elr_el1, ec);
+        SCHEDULER.lock().fault(SIGILL, e)
     }
 }
 
@@ -1,14 +1,24 @@
 use crate::global::{BCMDEVICES, SCHEDULER};
 use crate::exceptions::{syscalls, debug_halt};
+use crate::smp;
 use shared::exceptions::handlers::ExceptionContext;
 use tock_registers::interfaces::Readable;
 
 
+/// Per-core sources : the mailbox wakes up an idle core, the timer ticks, the UART goes to core 0.
 pub unsafe fn irq_handler(e: &ExceptionContext) {
-    let source = BCMDEVICES.CORE0_INTERRUPT_SOURCE.get();
-    match source {
-        2 => SCHEDULER.schedule(e),
-        0x100 => syscalls::reset(), // UART
-        _ => debug_halt("current_elx_irq", e)
-    };
-}
\ No newline at end of file
+    let source = BCMDEVICES.lock().CORE_INTERRUPT_SOURCE[smp::core_id()].get();
+    if source & 0x10 != 0 { // mailbox 0
+        smp::clear_wake();
+    }
+    if source & 0x100 != 0 { // UART
+        syscalls::uart_input();
+    }
+    let mut scheduler = SCHEDULER.lock();
+    if source & 0x2 != 0 || scheduler.is_idle() { // timer, or a process woken up for the idle core
+        scheduler.schedule(e)
+    }
+    if source & 0x112 == 0 {
+        debug_halt("current_elx_irq", e)
+    }
+}
@@ -1,36 +1,84 @@
+use alloc::string::String;
 use core::arch::asm;
-use core::slice;
-use core::str::from_utf8_unchecked;
+use core::str;
 use qemu_exit::QEMUExit;
 
 use aarch64_cpu::registers::{ESR_EL1, Readable, SP};
 use shared::exceptions::handlers::ExceptionContext;
 use crate::global::{SCHEDULER, UART};
-use mmio::io::Reader;
-use crate::scheduler::process::Process;
-use crate::scheduler::PROG_START;
-
-pub(crate) unsafe fn reset() {
-    let received = UART.read_char().unwrap_or(0) as char;
-    match received {
-        'r' => asm!("HVC 1"),
-        'h' => syscall_halt(),
-        _ => debug!("UART received : {}\n", received),
+use crate::scheduler::process::find_program;
+use mmio::syscall::{EFAULT, PRINT_MAX};
+
+/// Ctrl-R resets the board and Ctrl-X halts it, the other characters go to the processes.
+const RESET_KEY: u8 = 0x12;
+const HALT_KEY: u8 = 0x18;
+/// Longest path `exec` takes.
+const PATH_MAX: usize = 256;
+
+pub(crate) unsafe fn uart_input() {
+    loop {
+        // the UART is released before the scheduler is taken
+        let received = UART.lock().try_read_char();
+        match received {
+            Some(RESET_KEY) => asm!("HVC 1"),
+            Some(HALT_KEY) => syscall_halt(),
+            Some(c) => SCHEDULER.lock().receive(c),
+            None => break,
+        }
     }
 }
 
-pub(crate) unsafe fn syscalls(e : &ExceptionContext) {
+pub(crate) unsafe fn syscalls(e : &mut ExceptionContext) {
     match ESR_EL1.read(ESR_EL1::ISS) {
-        1 => syscall_print(e.gpr.x[0] as *const u8, e.gpr.x[1] as usize),
+        1 => syscall_print(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e),
         2 => syscall_halt(),
         3 => syscall_sleep(e.gpr.x[0], e),
+        4 => SCHEDULER.lock().fork(e),
+        5 => syscall_exec(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e),
+        6 => SCHEDULER.lock().exit(e.gpr.x[0] as i32, e),
+        7 => SCHEDULER.lock().waitpid(e.gpr.x[0] as i64, e),
+        8 => SCHEDULER.lock().set_priority(e.gpr.x[0] as u16, e.gpr.x[1] as i64, e),
+        9 => SCHEDULER.lock().brk(e.gpr.x[0] as usize, e),
+        10 => SCHEDULER.lock().mmap(e.gpr.x[0] as usize, e),
+        11 => SCHEDULER.lock().munmap(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e),
+        12 => SCHEDULER.lock().read(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e),
+        13 => SCHEDULER.lock().kill(e.gpr.x[0] as u16, e.gpr.x[1] as u32, e),
+        14 => SCHEDULER.lock().sigaction(e.gpr.x[0] as u32, e.gpr.x[1] as usize, e.gpr.x[2] as usize, e),
+        15 => SCHEDULER.lock().sigreturn(e),
+        16 => SCHEDULER.lock().getpid(e),
+        17 => SCHEDULER.lock().getppid(e),
+        18 => SCHEDULER.lock().proc_info(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e),
+        19 => SCHEDULER.lock().endpoint(e),
+        20 => SCHEDULER.lock().send(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e.gpr.x[2] as usize, e.gpr.x[3] as i64, e),
+        21 => SCHEDULER.lock().recv(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e.gpr.x[2] as usize, e),
+        22 => SCHEDULER.lock().call(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e.gpr.x[2] as usize,
+            e.gpr.x[3] as usize, e.gpr.x[4] as usize, e.gpr.x[5] as i64, e),
+        23 => SCHEDULER.lock().reply(e.gpr.x[0] as usize, e.gpr.x[1] as usize, e.gpr.x[2] as i64, e),
         _ => ()
     }
 }
 
-unsafe fn syscall_print(c_string: *const u8, len: usize) {
-    let string = slice::from_raw_parts(c_string, len);
-    print!("{}", from_utf8_unchecked(string));
+/// Print up to PRINT_MAX bytes at `buf`, returns how many were printed or EFAULT.
+unsafe fn syscall_print(buf: usize, len: usize, e: &mut ExceptionContext) {
+    let mut bytes = vec![0u8; len.min(PRINT_MAX)];
+    // the scheduler is released before printing
+    let copied = SCHEDULER.lock().current_process().copy_from_user(buf, &mut bytes);
+    if copied.is_err() {
+        e.gpr.x[0] = EFAULT as u64;
+        return;
+    }
+    let printed = match str::from_utf8(&bytes) {
+        Ok(string) => string.len(),
+        // a character cut at the end is printed with the next write
+        Err(err) if err.error_len().is_none() && err.valid_up_to() > 0 => err.valid_up_to(),
+        Err(_) => {
+            print!("{}", String::from_utf8_lossy(&bytes));
+            e.gpr.x[0] = bytes.len() as u64;
+            return;
+        }
+    };
+    print!("{}", str::from_utf8_unchecked(&bytes[..printed]));
+    e.gpr.x[0] = printed as u64;
 }
 
 
@@ -39,6 +87,22 @@ unsafe fn syscall_halt() {
     QEMU_EXIT_HANDLE.exit_success();
 }
 
+/// Returns EFAULT if `path` is out of the program memory, or -1 if it is not a program.
+unsafe fn syscall_exec(path: usize, len: usize, e: &mut ExceptionContext) {
+    let path = match SCHEDULER.lock().current_process().strncpy_from_user(path, len.min(PATH_MAX)) {
+        Ok(path) => path,
+        Err(_) => {
+            e.gpr.x[0] = EFAULT as u64;
+            return;
+        }
+    };
+    let program = str::from_utf8(&path).ok().and_then(find_program);
+    match program {
+        Some(bytes) => SCHEDULER.lock().exec(bytes, e),
+        None => e.gpr.x[0] = -1i64 as u64,
+    }
+}
+
 unsafe fn syscall_sleep(ms: u64, e: &ExceptionContext) {
-    SCHEDULER.sleep(core::ptr::read((PROG_START - 0x1000) as *const u16), ms, e);
+    SCHEDULER.lock().sleep(ms, e);
 }
\ No newline at end of file
@@ -1,15 +1,32 @@
 use mmio::{BCMDeviceMemory, Uart, PhysicalTimer, USB};
 use crate::memory;
 use qemu_exit::QEMUExit;
-use crate::scheduler::Scheduler;
+use crate::scheduler::{Scheduler, Core};
+use crate::memory::frames::FrameAllocator;
+use crate::initramfs::Initramfs;
+use shared::sync::IrqSafeMutex;
+use crate::scheduler::policy::Policy;
 use core::time::Duration;
+use linked_list_allocator::LockedHeap;
 
-pub const BCMDEVICES: BCMDeviceMemory = BCMDeviceMemory::new(memory::map::virt::peripheral::START);
-pub const USB: USB = USB::new(memory::map::virt::USB_BASE);
-pub const IRQ: mmio::IRQ = mmio::IRQ::new(memory::map::virt::IRQ_BASE);
-pub const UART: Uart = Uart::new(memory::map::virt::UART_BASE);
+pub static BCMDEVICES: IrqSafeMutex<BCMDeviceMemory> = IrqSafeMutex::new(BCMDeviceMemory::new(memory::map::virt::peripheral::START));
+pub static USB: IrqSafeMutex<USB> = IrqSafeMutex::new(USB::new(memory::map::virt::USB_BASE));
+pub static IRQ: IrqSafeMutex<mmio::IRQ> = IrqSafeMutex::new(mmio::IRQ::new(memory::map::virt::IRQ_BASE));
+pub static UART: IrqSafeMutex<Uart> = IrqSafeMutex::new(Uart::new(memory::map::virt::UART_BASE));
+/// Only the system registers of the running core, so it needs no lock
 pub const TIMER: PhysicalTimer = PhysicalTimer::new(Duration::from_millis(100));
-pub static mut SCHEDULER: Scheduler = Scheduler::new();
+#[global_allocator]
+pub static HEAP: LockedHeap = LockedHeap::empty();
+pub static FRAMES: IrqSafeMutex<FrameAllocator> = IrqSafeMutex::new(FrameAllocator::new());
+pub static INITRAMFS: IrqSafeMutex<Initramfs> = IrqSafeMutex::new(Initramfs::empty());
+/// The kernel is not reentrant : the exception handlers hold the scheduler until they return, or
+/// until the process or the idle task they switch to is restored.
+pub static SCHEDULER: IrqSafeMutex<Scheduler> = IrqSafeMutex::new(Scheduler::new([
+    Core::new(Policy::kernel()),
+    Core::new(Policy::kernel()),
+    Core::new(Policy::kernel()),
+    Core::new(Policy::kernel()),
+]));
 
 #[panic_handler]
 fn my_panic(info: &core::panic::PanicInfo) -> ! {
@@ -0,0 +1,113 @@
+use core::str::from_utf8;
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// A CPIO archive in the "newc" format (`find . | cpio -o -H newc`), holding the user programs.
+/// It is sent by the boot loader after the kernel.
+pub struct Initramfs {
+    bytes: &'static [u8],
+}
+
+/// A file of the archive.
+pub struct Entry {
+    pub name: &'static str,
+    pub mode: u32,
+    pub data: &'static [u8],
+}
+
+impl Initramfs {
+    pub const fn empty() -> Self {
+        Initramfs {
+            bytes: &[],
+        }
+    }
+
+    pub fn new(bytes: &'static [u8]) -> Result<Self, &'static str> {
+        let initramfs = Initramfs { bytes };
+        for entry in initramfs.entries() {
+            entry?;
+        }
+        Ok(initramfs)
+    }
+
+    /// Iterate over the files of the archive, until the trailer or the first malformed header.
+    pub fn entries(&self) -> Entries {
+        Entries {
+            bytes: self.bytes,
+            offset: 0,
+        }
+    }
+
+    /// Content of the regular file at `path`, "/bin/init" or "bin/init" alike.
+    pub fn find(&self, path: &str) -> Option<&'static [u8]> {
+        let path = normalize(path);
+        self.entries()
+            .map_while(|entry| entry.ok())
+            .find(|entry| normalize(entry.name) == path && entry.mode & 0o170000 == 0o100000)
+            .map(|entry| entry.data)
+    }
+}
+
+pub struct Entries {
+    bytes: &'static [u8],
+    offset: usize,
+}
+
+impl Entries {
+    fn parse(&mut self) -> Result<Option<Entry>, &'static str> {
+        let header = self.bytes.get(self.offset..self.offset + HEADER_SIZE).ok_or("CPIO header is out of the archive")?;
+        if &header[0..6] != NEWC_MAGIC {
+            return Err("not a CPIO newc archive");
+        }
+        let mode = field(header, 1)?;
+        let file_size = field(header, 6)? as usize;
+        let name_size = field(header, 11)? as usize;
+
+        let name_start = self.offset + HEADER_SIZE;
+        let name = self.bytes.get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or("CPIO file name is out of the archive")?;
+        let name = from_utf8(name).map_err(|_| "CPIO file name is not UTF-8")?;
+        let data_start = align4(name_start + name_size);
+        let data = self.bytes.get(data_start..data_start + file_size).ok_or("CPIO file is out of the archive")?;
+        self.offset = align4(data_start + file_size);
+
+        if name == TRAILER {
+            Ok(None)
+        } else {
+            Ok(Some(Entry { name, mode, data }))
+        }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        let entry = self.parse();
+        if !matches!(entry, Ok(Some(_))) {
+            // nothing is read after the trailer or a malformed header
+            self.offset = self.bytes.len();
+        }
+        entry.transpose()
+    }
+}
+
+/// Value of the `index`th field of a header, written with 8 hexadecimal digits after the magic.
+fn field(header: &[u8], index: usize) -> Result<u32, &'static str> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let digits = from_utf8(&header[start..start + 8]).map_err(|_| "CPIO header field is not hexadecimal")?;
+    u32::from_str_radix(digits, 16).map_err(|_| "CPIO header field is not hexadecimal")
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
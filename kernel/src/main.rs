@@ -1,25 +1,27 @@
 #![no_std]
 #![no_main]
-#![feature(core_intrinsics)]
 #![feature(duration_constants)]
 #![feature(alloc_error_handler)]
 
 #[macro_use] extern crate alloc;
 #[macro_use] extern crate mmio;
 
-use aarch64_cpu::asm;
 
 use memory::descriptors::{KERNEL_VIRTUAL_LAYOUT, PROGRAM_VIRTUAL_LAYOUT};
-use mmio::{DMA, HEAP, IRQ};
+use mmio::{DMA, IRQ};
+use tock_registers::interfaces::Writeable;
 use shared::memory::mmu::{VIRTUAL_ADDR_START};
 
-use crate::global::{BCMDEVICES, UART, TIMER};
-use crate::scheduler::process::{create_init_program, create_tmp_init_program};
+use crate::global::{BCMDEVICES, UART, TIMER, FRAMES, HEAP, INITRAMFS, SCHEDULER};
+use crate::initramfs::Initramfs;
+use crate::scheduler::process::create_init_program;
 
 mod memory;
 mod exceptions;
 mod global;
 mod scheduler;
+mod initramfs;
+mod smp;
 
 extern "C" {
     // Boundaries of the .bss section, provided by the linker script
@@ -27,55 +29,86 @@ extern "C" {
     static mut __bss_end: u64;
 }
 
-/// Entrypoint of the kernel.
+/// Entrypoint of the kernel, the boot loader gives the physical address and the size of the
+/// initramfs.
 #[link_section = ".text.boot"]
 #[no_mangle]
-pub unsafe extern "C" fn _upper_kernel() -> ! {
+pub unsafe extern "C" fn _upper_kernel(initramfs_start: usize, initramfs_len: usize) -> ! {
 
     r0::zero_bss(&mut __bss_start, &mut __bss_end);
+    shared::sync::enable_exception_masking();
+    match memory::init_layout() {
+        Err(err) => panic!("memory layout failed : {}", err),
+        _ => {}
+    }
     match setup_mmu() {
         Err(err) => panic!("setup mmu failed : {}", err),
         _ => {}
     }
     exceptions::init();
+    scheduler::fp::trap();
     unsafe {
-        DMA.lock().init(memory::map::physical::MMA_MEMORY_START,
-                        memory::map::physical::MMA_MEMORY_END - memory::map::physical::MMA_MEMORY_START);
+        let mma = &memory::layout().mma;
+        DMA.lock().init(*mma.start(), mma.end() - mma.start());
     }
     let v_mbox = mmio::Mbox::new_with_dma(memory::map::virt::MBOX_BASE);
-    mmio::LOGGER.appender(UART.into());
+    mmio::LOGGER.lock().appender((*UART.lock()).into());
     let console = mmio::FrameBufferConsole::new(v_mbox, VIRTUAL_ADDR_START);
-    mmio::SCREEN.appender( console.into());
+    mmio::SCREEN.lock().appender( console.into());
 
     unsafe { print!("MMU Kernel mapping : \n{}", shared::memory::mmu::kernel_tables()); }
     unsafe { print!("MMU Program mapping : \n{}", shared::memory::mmu::user_tables()); }
+    if !shared::memory::mmu::has_pan() {
+        print!("PAN is not available, the kernel can access the user memory\n");
+    }
 
     unsafe {
-        HEAP.lock().init(memory::map::virt::KERNEL_HEAP_START,
-                         memory::map::virt::KERNEL_HEAP_END - memory::map::virt::KERNEL_HEAP_START);
+        let heap = &memory::layout().heap;
+        HEAP.lock().init(memory::phys_to_virt(*heap.start()), heap.end() - heap.start());
+    }
+    {
+        let mut frames = FRAMES.lock();
+        frames.init(memory::layout().user.clone());
+        print!("User memory : {} frames\n", frames.free_frames());
+    }
+    match SCHEDULER.lock().init_idle_tables() {
+        Err(err) => panic!("idle tables failed : {}", err),
+        _ => {}
+    }
+
+    {
+        let len = initramfs_len.min(memory::map::physical::INITRAMFS_END + 1 - initramfs_start);
+        let bytes = core::slice::from_raw_parts(memory::phys_to_virt(initramfs_start) as *const u8, len);
+        let mut initramfs = INITRAMFS.lock();
+        match Initramfs::new(bytes) {
+            Ok(parsed) => *initramfs = parsed,
+            Err(err) => print!("Initramfs is invalid : {}\n", err),
+        }
+        print!("Initramfs : {} files\n", initramfs.entries().count());
     }
 
     // setup IRQs
-    //UART.enable_rx_irq(&irq, &bcm);
+    unsafe { UART.lock().enable_rx_irq(&global::IRQ.lock(), &BCMDEVICES.lock()); }
 
-    create_tmp_init_program();
-    create_tmp_init_program();
-    create_tmp_init_program();
-    create_tmp_init_program();
-    create_tmp_init_program();
 
     create_init_program();
 
-    TIMER.setup(&BCMDEVICES);
+    TIMER.setup(&BCMDEVICES.lock(), 0);
+    BCMDEVICES.lock().CORE_MAILBOX_IRQCNTL[0].set(1);
+    SCHEDULER.lock().start_core(0);
+    match smp::start_cores() {
+        Err(err) => panic!("secondary cores failed : {}", err),
+        _ => {}
+    }
     unsafe { IRQ::enable(); }
 
-    loop {
-        asm::wfi();
-    }
+    // the boot code becomes the idle task, until the first tick schedules the init program
+    scheduler::idle::idle()
 }
 
 fn setup_mmu() -> Result<(), &'static str> {
     shared::memory::mmu::setup_kernel_tables(&KERNEL_VIRTUAL_LAYOUT)?;
     shared::memory::mmu::setup_user_tables(&PROGRAM_VIRTUAL_LAYOUT)?;
+    shared::memory::mmu::enable_protections();
     Ok(())
 }
@@ -1,4 +1,62 @@
 pub mod descriptors;
+pub mod frames;
+
+use core::ops::RangeInclusive;
+use shared::memory::mmu::{Granule64KiB, TranslationGranule};
+
+/// DRAM regions which depend on the memory split between the ARM cores and the VideoCore.
+pub struct Layout {
+    pub heap: RangeInclusive<usize>,
+    pub mma: RangeInclusive<usize>,
+    pub user: RangeInclusive<usize>,
+    pub gpu: RangeInclusive<usize>,
+}
+
+static mut LAYOUT: Layout = Layout {
+    heap: 0..=0,
+    mma: 0..=0,
+    user: 0..=0,
+    gpu: 0..=0,
+};
+
+/// Physical memory layout, set up by `init_layout`.
+pub fn layout() -> &'static Layout {
+    unsafe { &*core::ptr::addr_of!(LAYOUT) }
+}
+
+/// Ask the VideoCore how the memory is split, and carve the heap, the MMA (DMA) memory and the
+/// user memory out of the ARM memory left after the kernel.
+pub fn init_layout() -> Result<(), &'static str> {
+    // no DMA memory yet, the buffer is on the stack : the mailbox translates it to its physical
+    // address and keeps the caches in sync around the call
+    let mut v_mbox = mmio::Mbox::new(map::virt::MBOX_BASE);
+    let (arm_base, arm_size) = v_mbox.arm_memory().map_err(|_| "can not read the ARM memory from the mailbox")?;
+    let (vc_base, vc_size) = v_mbox.vc_memory().map_err(|_| "can not read the VideoCore memory from the mailbox")?;
+
+    let heap_start = map::physical::KERN_END + 1;
+    let mma_start = heap_start + map::physical::KERNEL_HEAP_SIZE;
+    let user_start = mma_start + map::physical::MMA_MEMORY_SIZE;
+    let user_end = ((arm_base + arm_size) & Granule64KiB::ALIGN) - 1;
+    if arm_base > map::physical::KERN_START || user_end <= user_start {
+        return Err("not enough ARM memory for the kernel");
+    }
+
+    unsafe {
+        LAYOUT = Layout {
+            heap: heap_start..=mma_start - 1,
+            mma: mma_start..=user_start - 1,
+            user: user_start..=user_end,
+            gpu: vc_base..=vc_base + vc_size - 1,
+        };
+    }
+    Ok(())
+}
+
+/// Kernel address of a physical address, through the identity mapping of the kernel tables.
+#[inline]
+pub fn phys_to_virt(addr: usize) -> usize {
+    map::virt::START + addr
+}
 
 /// System memory map.
 #[allow(dead_code)]
@@ -8,20 +66,23 @@ pub mod map {
     pub const END:                     usize =             0xFFFF_FFFF;
 
     pub mod physical {
-        pub const KERNEL_HEAP_START:   usize =             0x3500_0000;
-        pub const KERNEL_HEAP_END:     usize =             0x36FF_FFFF;
+        /// Page of the firmware spin table, where the secondary cores wait to be released
+        pub const SPIN_TABLE_START:    usize =             0x0000_0000;
+        pub const SPIN_TABLE_END:      usize =             0x0000_FFFF;
 
-        pub const MMA_MEMORY_START:    usize =             0x3700_0000;
-        pub const MMA_MEMORY_END:      usize =             0x38FF_FFFF;
+        /// Archive of the user programs, written by the boot loader below the kernel
+        pub const INITRAMFS_START:     usize =             0x0080_0000;
+        pub const INITRAMFS_END:       usize =             0x00FF_FFFF;
 
-        pub const KERN_START:          usize =             0x3900_0000;
-        pub const KERN_END:            usize =             0x3AFF_FFFF;
+        pub const KERN_START:          usize =             0x0100_0000;
+        pub const KERN_END:            usize =             0x02FF_FFFF;
 
-        pub const KERN_STACK_START:    usize =             0x3AF8_0000;
-        pub const KERN_STACK_END:      usize =             0x3AFF_FFFF;
+        pub const KERN_STACK_START:    usize =             0x02F8_0000;
+        pub const KERN_STACK_END:      usize =             0x02FF_FFFF;
 
-        pub const GPU_BASE:            usize =             0x3B00_0000;
-        pub const GPU_END:             usize =             0x3EFF_FFFF;
+        /// Sizes of the regions carved right after the kernel, the user memory gets the rest
+        pub const KERNEL_HEAP_SIZE:    usize =             0x0200_0000;
+        pub const MMA_MEMORY_SIZE:     usize =             0x0200_0000;
 
         pub const MMIO_BASE:           usize =             0x3F00_0000;
         pub const IRQ_BASE:            usize = MMIO_BASE + 0x0000_B200;
@@ -40,10 +101,6 @@ pub mod map {
         use shared::memory::mmu::VIRTUAL_ADDR_START;
 
         pub const START:               usize =   VIRTUAL_ADDR_START;
-        pub const KERNEL_HEAP_START:   usize =  START + super::physical::KERNEL_HEAP_START;
-        pub const KERNEL_HEAP_END:     usize =  START + super::physical::KERNEL_HEAP_END;
-        pub const MMA_MEMORY_START:    usize =  START + super::physical::MMA_MEMORY_START;
-        pub const MMA_MEMORY_END:      usize =  START + super::physical::MMA_MEMORY_END;
         pub const KERN_START:          usize =  START + super::physical::KERN_START;
         pub const KERN_STACK_START:    usize =  START + super::physical::KERN_STACK_START;
 
@@ -3,25 +3,47 @@ use shared::memory::mapping::{Translation, Mapping, MemAttributes,
                               AccessPermissions, Descriptor, AttributeFields};
 use shared::memory::mmu::VIRTUAL_ADDR_START;
 
+extern "C" {
+    // End of the code and read only data, aligned on a page by the linker script
+    static __ro_end: u64;
+}
+
+/// Physical end of the code and read only data of the kernel.
+fn ro_end() -> usize {
+    unsafe { &__ro_end as *const _ as usize & !VIRTUAL_ADDR_START }
+}
+
 /// A virtual memory layout that is agnostic of the paging granularity that the
 /// hardware MMU will use.
 ///
-pub static KERNEL_VIRTUAL_LAYOUT: [Descriptor; 6] = [
-    //Kernel
+pub static KERNEL_VIRTUAL_LAYOUT: [Descriptor; 9] = [
+    //Kernel code
     Descriptor {
-        virtual_range: || RangeInclusive::new(super::map::physical::KERN_START, super::map::physical::KERN_STACK_START - 1),
+        virtual_range: || RangeInclusive::new(super::map::physical::KERN_START, ro_end() - 1),
         map: Mapping {
             translation: Translation::Identity,
             attribute_fields: AttributeFields {
                 mem_attributes: MemAttributes::CacheableDRAM,
-                acc_perms: AccessPermissions::ReadWriteKernel,
+                acc_perms: AccessPermissions::ReadOnlyKernel,
                 execute_never: false,
             },
         },
     },
+    //Kernel data
+    Descriptor {
+        virtual_range: || RangeInclusive::new(ro_end(), super::map::physical::KERN_STACK_START - 1),
+        map: Mapping {
+            translation: Translation::Identity,
+            attribute_fields: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWriteKernel,
+                execute_never: true,
+            },
+        },
+    },
     //Stack Heap
     Descriptor {
-        virtual_range: || RangeInclusive::new(super::map::physical::KERNEL_HEAP_START, super::map::physical::KERNEL_HEAP_END),
+        virtual_range: || super::layout().heap.clone(),
         map: Mapping {
             translation: Translation::Identity,
             attribute_fields: AttributeFields {
@@ -66,9 +88,33 @@ pub static KERNEL_VIRTUAL_LAYOUT: [Descriptor; 6] = [
             },
         },
     },
-    // Program Low memory
+    // Initramfs, only read to load the programs
+    Descriptor {
+        virtual_range: || RangeInclusive::new(super::map::physical::INITRAMFS_START, super::map::physical::INITRAMFS_END),
+        map: Mapping {
+            translation: Translation::Identity,
+            attribute_fields: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadOnlyKernel,
+                execute_never: true,
+            },
+        },
+    },
+    // Spin table, to release the secondary cores
+    Descriptor {
+        virtual_range: || RangeInclusive::new(super::map::physical::SPIN_TABLE_START, super::map::physical::SPIN_TABLE_END),
+        map: Mapping {
+            translation: Translation::Identity,
+            attribute_fields: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWriteKernel,
+                execute_never: true,
+            },
+        },
+    },
+    // User memory, so the kernel can fill and copy program pages
     Descriptor {
-        virtual_range: || RangeInclusive::new(0x20_0000, 0x150_0000),
+        virtual_range: || super::layout().user.clone(),
         map: Mapping {
             translation: Translation::Identity,
             attribute_fields: AttributeFields {
@@ -83,7 +129,7 @@ pub static KERNEL_VIRTUAL_LAYOUT: [Descriptor; 6] = [
 pub static PROGRAM_VIRTUAL_LAYOUT: [Descriptor; 2] = [
     // MMA memory
     Descriptor {
-        virtual_range: || RangeInclusive::new(super::map::physical::MMA_MEMORY_START, super::map::physical::MMA_MEMORY_END),
+        virtual_range: || super::layout().mma.clone(),
         map: Mapping {
             translation: Translation::Identity,
             attribute_fields: AttributeFields {
@@ -95,7 +141,7 @@ pub static PROGRAM_VIRTUAL_LAYOUT: [Descriptor; 2] = [
     },
     // GPU Ram
     Descriptor {
-        virtual_range: || RangeInclusive::new(super::map::physical::GPU_BASE, super::map::physical::GPU_END),
+        virtual_range: || super::layout().gpu.clone(),
         map: Mapping {
             translation: Translation::Identity,
             attribute_fields: AttributeFields {
@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use shared::memory::mmu::{TranslationGranule, UserGranule};
+use shared::memory::walker::TableAllocator;
+use crate::memory::phys_to_virt;
+
+/// Physical memory handed out to the processes, one 4KiB frame at a time. A bit is set in the
+/// bitmap for every frame in use, and frames shared by several processes are reference counted.
+pub struct FrameAllocator {
+    start: usize,
+    nb_frames: usize,
+    bitmap: Vec<u64>,
+    references: Vec<u16>,
+}
+
+impl FrameAllocator {
+    pub const fn new() -> Self {
+        FrameAllocator {
+            start: 0,
+            nb_frames: 0,
+            bitmap: Vec::new(),
+            references: Vec::new(),
+        }
+    }
+
+    /// Manage the physical `range`, the heap must be set up as the bitmap lives in it.
+    pub fn init(&mut self, range: RangeInclusive<usize>) {
+        self.start = (range.start() + UserGranule::MASK) & UserGranule::ALIGN;
+        self.nb_frames = (range.end() + 1 - self.start) >> UserGranule::SHIFT;
+        self.bitmap = vec![0; (self.nb_frames + 63) / 64];
+        self.references = vec![0; self.nb_frames];
+    }
+
+    /// Physical address of a free frame, its content is undefined.
+    pub fn alloc(&mut self) -> Option<usize> {
+        let (word_nr, word) = self.bitmap.iter_mut()
+            .enumerate()
+            .find(|(_, word)| **word != u64::MAX)?;
+        let frame = word_nr * 64 + word.trailing_ones() as usize;
+        if frame >= self.nb_frames {
+            return None;
+        }
+        *word |= 1 << (frame % 64);
+        self.references[frame] = 1;
+        Some(self.start + (frame << UserGranule::SHIFT))
+    }
+
+    /// Drop a reference to a frame, it is free once nobody references it.
+    pub fn free(&mut self, addr: usize) {
+        let frame = self.frame_nr(addr);
+        self.references[frame] -= 1;
+        if self.references[frame] == 0 {
+            self.bitmap[frame / 64] &= !(1 << (frame % 64));
+        }
+    }
+
+    /// Add a reference to a frame in use, when it is shared by one more process.
+    pub fn share(&mut self, addr: usize) {
+        let frame = self.frame_nr(addr);
+        self.references[frame] += 1;
+    }
+
+    /// Number of processes using a frame.
+    pub fn references(&self, addr: usize) -> u16 {
+        self.references[self.frame_nr(addr)]
+    }
+
+    fn frame_nr(&self, addr: usize) -> usize {
+        let frame = (addr - self.start) >> UserGranule::SHIFT;
+        assert!(frame < self.nb_frames, "frame is out of the allocator");
+        frame
+    }
+
+    /// Number of frames which can still be allocated.
+    pub fn free_frames(&self) -> usize {
+        self.nb_frames - self.bitmap.iter().map(|word| word.count_ones() as usize).sum::<usize>()
+    }
+}
+
+/// Translation tables of the processes take a whole frame, whatever their granule.
+impl TableAllocator for FrameAllocator {
+    fn alloc_table(&mut self) -> Option<usize> {
+        self.alloc()
+    }
+
+    fn free_table(&mut self, table: usize) {
+        self.free(table)
+    }
+
+    fn table_addr(&self, table: usize) -> usize {
+        phys_to_virt(table)
+    }
+}
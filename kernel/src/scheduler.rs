@@ -1,92 +1,897 @@
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
-use core::intrinsics::{copy, size_of, transmute};
 
-use process::Process;
+use process::{Process, ProcessContext, ProcessState, BlockReason, Handle};
+use policy::{Policy, NICE_MIN, NICE_MAX};
+use fp::FpState;
+use wait::WaitQueue;
+use signal::{Action, Signals};
+use ipc::{Endpoint, Message};
+use mmio::syscall::{SIGSEGV, SIG_DFL, ProcInfo, MSG_MAX, NO_HANDLE, EFAULT};
+use core::mem::size_of;
+use core::slice;
+use elf::Elf;
 use crate::memory::descriptors::PROGRAM_VIRTUAL_LAYOUT;
-use shared::memory::mapping::{Descriptor, Mapping, Translation, AttributeFields, MemAttributes, AccessPermissions};
-use core::ops::RangeInclusive;
-use aarch64_cpu::asm::wfi;
+use core::time::Duration;
 use shared::exceptions::handlers::ExceptionContext;
-use crate::global::TIMER;
-use aarch64_cpu::registers::{CNTV_TVAL_EL0, Readable};
+use crate::global::{TIMER, FRAMES};
+use crate::smp::{self, NB_CORES};
+use shared::memory::mmu::{switch_user_granule, switch_user_tables, UserGranule, VA_BITS};
+use shared::memory::walker::PageTable;
 
 pub mod process;
+pub mod policy;
+pub mod fp;
+pub mod wait;
+pub mod idle;
+pub mod signal;
+pub mod ipc;
+mod elf;
 
 pub const PROG_START: usize = 0x0020_0000;
 pub const PROG_END:   usize = 0x0040_0000;
+/// Stacks grow down from STACK_TOP, with an unmapped guard page below them
+pub const STACK_TOP:  usize = 0x1000_0000;
+pub const DEFAULT_STACK_SIZE: usize = 0x0004_0000;
+pub const MAX_STACK_SIZE: usize = 0x00F0_0000;
+/// Memory given by brk, then by mmap, away from the identity mapped MMA memory
+pub const HEAP_START: usize = 0x1000_0000;
+pub const HEAP_END:   usize = 0x17FF_FFFF;
+pub const MMAP_START: usize = 0x1800_0000;
+pub const MMAP_END:   usize = 0x1FFF_FFFF;
+
+/// Scheduling state of a core.
+pub struct Core {
+    /// PID of the process running on the core, 0 when it is idle
+    current: u16,
+    /// PID of the process whose FP/SIMD state is in the registers of the core, 0 for none
+    fp_owner: u16,
+    /// runnable processes queued on the core
+    policy: Policy,
+    /// processes are only queued on the started cores
+    online: bool,
+}
+
+impl Core {
+    /// Each core gets its own run queue, with its own instance of the policy.
+    pub const fn new(policy: Policy) -> Self {
+        Core {
+            current: 0,
+            fp_owner: 0,
+            policy,
+            online: false,
+        }
+    }
+}
 
 pub struct Scheduler {
     processes: Vec<Process>,
-    pid: u16
+    /// last PID given
+    pid: u16,
+    cores: [Core; NB_CORES],
+    /// sleeping processes as (deadline, pid), sorted by deadline
+    wakeups: Vec<(u64, u16)>,
+    /// characters received by the UART, not read yet
+    input: VecDeque<u8>,
+    /// processes blocked until the UART receives something
+    uart_readers: WaitQueue,
+    endpoints: Vec<Endpoint>,
+    /// id of the last endpoint created
+    endpoint_id: u32,
+    /// id of the last call made, a reply only answers the call it was received with
+    call_id: u32,
+    /// physical address of the user tables of the idle cores, without any process memory
+    idle_tables: usize,
 }
 
 impl Scheduler {
-    pub const fn new () -> Self {
+    pub const fn new (cores: [Core; NB_CORES]) -> Self {
         Scheduler {
             processes: Vec::new(),
             pid: 0,
+            cores,
+            wakeups: Vec::new(),
+            input: VecDeque::new(),
+            uart_readers: WaitQueue::new(),
+            endpoints: Vec::new(),
+            endpoint_id: 0,
+            call_id: 0,
+            idle_tables: 0,
+        }
+    }
+
+    /// Build the user tables of the idle cores, and move the running core to them. The frame
+    /// allocator must be set up.
+    pub fn init_idle_tables(&mut self) -> Result<(), &'static str> {
+        let mut frames = FRAMES.lock();
+        let mut tables = PageTable::<UserGranule>::new(&mut *frames, VA_BITS)?;
+        tables.map_descriptors(&mut *frames, &PROGRAM_VIRTUAL_LAYOUT)?;
+        self.idle_tables = tables.phys_base_addr();
+        switch_user_granule(self.idle_tables as u64);
+        Ok(())
+    }
+
+    pub fn idle_tables(&self) -> u64 {
+        self.idle_tables as u64
+    }
+
+    /// Create a process from an ELF executable, its segments are loaded in frames taken from
+    /// the user memory.
+    pub fn create_process(&mut self, bytes: &[u8]) -> Result<u16, &'static str> {
+        let elf = Elf::parse(bytes)?;
+        let current_pid = self.free_pid().ok_or("no PID left")?;
+        let mut created_process = Process::new(current_pid, 0)?;
+        created_process.init_local_tlb(&PROGRAM_VIRTUAL_LAYOUT)?;
+        created_process.load(&elf)?;
+        let nice = created_process.nice();
+        self.processes.push(created_process);
+        self.enqueue(current_pid, nice);
+        self.pid = current_pid;
+        Ok(current_pid)
+    }
+
+    /// PID for a new process, after the last one given. The PIDs wrap around, skipping 0 and the
+    /// ones still in use.
+    fn free_pid(&self) -> Option<u16> {
+        (1..=u16::MAX)
+            .map(|i| self.pid.wrapping_add(i))
+            .find(|&pid| pid != 0 && !self.processes.iter().any(|p| p.pid == pid))
+    }
+
+    /// Let the running core take processes from now on.
+    pub fn start_core(&mut self, core: usize) {
+        self.cores[core].online = true;
+    }
+
+    pub unsafe fn schedule(&mut self, e: &ExceptionContext) -> ! {
+
+        TIMER.reset_counter();
+
+        if !self.is_idle() && self.current_process().is_running() {
+            let process = self.current_process();
+            process.pause(&e.gpr, e.spsr_el1, e.elr_el1, e.stack_el0);
+            let (pid, nice) = (process.pid, process.nice());
+            self.enqueue(pid, nice);
+        }
+
+        self.run_next(e)
+    }
+
+    /// Queue a runnable process on the core holding its FP/SIMD registers, or else on the least
+    /// busy core, which is woken up if it is idle.
+    fn enqueue(&mut self, pid: u16, nice: i8) {
+        let running = smp::core_id();
+        let core = match self.cores.iter().position(|c| c.fp_owner == pid) {
+            Some(core) => core,
+            None => (0..NB_CORES)
+                .map(|i| (running + i) % NB_CORES)
+                .filter(|&c| self.cores[c].online)
+                .min_by_key(|&c| self.cores[c].policy.len() + (self.cores[c].current != 0) as usize)
+                .unwrap_or(running),
+        };
+        self.cores[core].policy.enqueue(pid, nice);
+        if core != running && self.cores[core].current == 0 {
+            smp::wake_core(core);
         }
     }
 
-    pub fn create_process(&mut self, bytes: &[u8]) {
-        let current_pid = self.pid + 1;
-        let mut descriptors = PROGRAM_VIRTUAL_LAYOUT.to_vec();
-        descriptors.push(Descriptor {
-            virtual_range: || RangeInclusive::new(PROG_START, PROG_END - 1),
-            map : Mapping {
-                translation: Translation::Offset(0x100_0000 * current_pid as usize),
-                attribute_fields: AttributeFields {
-                    mem_attributes: MemAttributes::CacheableDRAM,
-                    acc_perms: AccessPermissions::ReadWriteUser,
-                    execute_never: false,
+    /// Next process queued on the running core, or taken from the queue of another core. The
+    /// processes holding the FP/SIMD registers of their core are left to it.
+    fn next_pid(&mut self) -> Option<u16> {
+        let running = smp::core_id();
+        if let Some(pid) = self.cores[running].policy.next() {
+            return Some(pid);
+        }
+        for core in (0..NB_CORES).filter(|&c| c != running) {
+            match self.cores[core].policy.next() {
+                Some(pid) if pid == self.cores[core].fp_owner => {
+                    let nice = self.processes.iter().find(|p| p.pid == pid).map_or(0, |p| p.nice());
+                    self.cores[core].policy.enqueue(pid, nice);
                 },
-            },
-        });
-        descriptors.push(Descriptor {
-            virtual_range: || RangeInclusive::new(PROG_START - 0x1000, PROG_START - 1),
-            map : Mapping {
-                translation: Translation::Offset(0x100_0000 * current_pid as usize - 0x1000),
-                attribute_fields: AttributeFields {
-                    mem_attributes: MemAttributes::CacheableDRAM,
-                    acc_perms: AccessPermissions::ReadWriteKernel,
-                    execute_never: true,
+                Some(pid) => return Some(pid),
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// Restore the process picked by the scheduling policy, if there is none, switch to the idle
+    /// task. The idle task is tickless : the timer only fires for the next sleeping process.
+    fn run_next(&mut self, e: &ExceptionContext) -> ! {
+        self.wake_sleeping();
+        while let Some(pid) = self.next_pid() {
+            // skip the processes which stopped being runnable since they were queued
+            if let Some(index) = self.processes.iter().position(|p| p.pid == pid && p.is_runnable()) {
+                self.core().current = pid;
+                self.deliver_signals(e);
+                let fp_owner = self.core().fp_owner;
+                let p = &mut self.processes[index];
+                if p.pid == fp_owner { fp::enable() } else { fp::trap() }
+                p.restore(e.stack_el1)
+            }
+        }
+        self.core().current = 0;
+        switch_user_tables(0, self.idle_tables as u64);
+        match self.wakeups.first() {
+            Some(&(deadline, _)) => TIMER.set_deadline(deadline),
+            None => TIMER.stop(),
+        }
+        idle::run(e.stack_el1)
+    }
+
+    /// Apply the pending signals of the process about to resume : it enters the handlers, or the
+    /// fatal signals without handler terminate it.
+    fn deliver_signals(&mut self, e: &ExceptionContext) {
+        let fp_owner = self.core().fp_owner;
+        let process = self.current_process();
+        while let Some(sig) = process.signals.take_pending() {
+            let delivered = match process.signals.action(sig) {
+                Action::Handler { handler, restorer } => {
+                    // the FP/SIMD registers of the process may only be in the core
+                    if process.pid == fp_owner {
+                        process.fp.save();
+                    }
+                    process.enter_handler(sig, handler, restorer)
                 },
-            },
+                Action::Default if Signals::is_fatal(sig) => Err("terminated"),
+                _ => Ok(()),
+            };
+            if let Err(err) = delivered {
+                debugln!("process {} killed by signal {} : {}", process.pid, sig, err);
+                self.exit(128 + sig as i32, e);
+            }
+        }
+    }
+
+    /// True when the idle task runs on the core, the interruptions waking up a process must then
+    /// call `schedule` as no timer tick may come.
+    pub fn is_idle(&self) -> bool {
+        self.current() == 0
+    }
+
+    fn core(&mut self) -> &mut Core {
+        &mut self.cores[smp::core_id()]
+    }
+
+    /// PID of the process running on the core, 0 when it is idle.
+    fn current(&self) -> u16 {
+        self.cores[smp::core_id()].current
+    }
+
+    /// Wake up the processes whose deadline has passed.
+    fn wake_sleeping(&mut self) {
+        let now = TIMER.now();
+        let expired = self.wakeups.partition_point(|&(deadline, _)| deadline <= now);
+        let expired: Vec<(u64, u16)> = self.wakeups.drain(..expired).collect();
+        for (deadline, pid) in expired {
+            match self.processes.iter_mut().find(|p| p.pid == pid) {
+                // a process woken up by a signal may sleep again, until another deadline
+                Some(p) if p.state() == ProcessState::Sleeping(deadline) => {
+                    p.wake();
+                    let (pid, nice) = (p.pid, p.nice());
+                    self.enqueue(pid, nice);
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn running_index(&self) -> usize {
+        let current = self.current();
+        self.processes.iter().position(|p| p.pid == current).expect("no process is running")
+    }
+
+    /// Control block of the running process.
+    pub fn current_process(&mut self) -> &mut Process {
+        let index = self.running_index();
+        &mut self.processes[index]
+    }
+
+    /// Duplicate the running process, the child starts from the same syscall with 0 as result
+    /// while the parent gets the child PID.
+    pub fn fork(&mut self, e: &mut ExceptionContext) {
+        let child_pid = match self.free_pid() {
+            Some(pid) => pid,
+            None => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let index = self.running_index();
+        let fp_owner = self.core().fp_owner;
+        let parent = &mut self.processes[index];
+        if parent.pid == fp_owner {
+            parent.fp.save();
+        }
+        let created = Process::new(child_pid, parent.pid).and_then(|mut child| {
+            child.init_local_tlb(&PROGRAM_VIRTUAL_LAYOUT)?;
+            child.copy_memory(parent)?;
+            Ok(child)
         });
-        let process = Process::new(current_pid);
-        self.processes.push(process); // move the process from stack to heap and allow to have several table entry ...
-        let created_process = self.processes.last_mut().expect("created process not working properly");
-        created_process.init_local_tlb(&descriptors);
-        unsafe {
-            copy(bytes.as_ptr(), PROG_START as *mut u8, bytes.len());
-            core::ptr::write((PROG_START - 0x1000) as * mut u16, created_process.pid); // todo : write the whole process in the zone (size > 0x1000)
+        let mut child = match created {
+            Ok(child) => child,
+            Err(_) => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        child.set_nice(parent.nice());
+        child.credentials = parent.credentials;
+        child.handles = parent.handles.clone();
+        child.fp = parent.fp;
+        child.signals = parent.signals.inherit();
+
+        let mut context = ProcessContext::new(e.gpr, e.spsr_el1, e.elr_el1, e.stack_el0);
+        context.regs.x[0] = 0;
+        child.set_context(context);
+        let nice = child.nice();
+        self.processes.push(child);
+        self.enqueue(child_pid, nice);
+        self.pid = child_pid;
+
+        e.gpr.x[0] = child_pid as u64;
+    }
+
+    /// Replace the program of the running process, returns only if `bytes` is not a valid program.
+    pub fn exec(&mut self, bytes: &[u8], e: &mut ExceptionContext) {
+        let elf = match Elf::parse(bytes) {
+            Ok(elf) => elf,
+            Err(_) => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let index = self.running_index();
+        let process = &mut self.processes[index];
+        process.clear_local_tlb();
+        let loaded = process.init_local_tlb(&PROGRAM_VIRTUAL_LAYOUT)
+            .and_then(|_| process.load(&elf));
+        if loaded.is_err() {
+            // the previous program is gone, nothing to return to
+            self.exit(-1, e);
         }
-        self.pid = current_pid;
+        process.fp = FpState::new();
+        process.signals.reset_actions();
+        let core = self.core();
+        if core.fp_owner == core.current {
+            core.fp_owner = 0;
+        }
+        fp::trap();
+        self.processes[index].restore(e.stack_el1)
     }
 
-    pub unsafe fn schedule(&mut self, e: &ExceptionContext) {
+    /// Terminate the running process, its parent is woken up if it waits for it.
+    pub fn exit(&mut self, code: i32, e: &ExceptionContext) -> ! {
+        let index = self.running_index();
+        let pid = self.processes[index].pid;
+        let parent = self.processes[index].parent;
+        self.processes[index].exit(code);
+        self.cores.iter_mut()
+            .filter(|c| c.fp_owner == pid)
+            .for_each(|c| c.fp_owner = 0);
+        // nothing will answer the callers waiting for the process, or for the endpoints only it held
+        if let Some((caller, call)) = self.processes[index].reply_to.take() {
+            self.fail_caller(caller, call);
+        }
+        self.cancel_calls(pid, None);
+        self.release_endpoints();
+        let accounting = self.processes[index].accounting;
+        debugln!("process {} exited with {}, cpu time {} out of {} ticks", pid, code,
+            accounting.cpu_time, TIMER.now() - accounting.started);
 
-        TIMER.reset_counter();
+        // orphans are adopted by the kernel, which does not wait for them
+        self.processes.retain(|p| !(p.parent == pid && matches!(p.state(), ProcessState::Zombie(_))));
+        self.processes.iter_mut()
+            .filter(|p| p.parent == pid)
+            .for_each(|p| p.parent = 0);
 
-        match self.processes.iter_mut()
-            .find(|p| p.is_running()) {
-            Some(p) => p.pause(&e.gpr, e.spsr_el1, e.elr_el1, e.stack_el0),
-            _ => {}
+        match self.processes.iter_mut().find(|p| p.pid == parent) {
+            Some(p) if matches!(p.state(), ProcessState::Blocked(BlockReason::WaitChild(w)) if w == -1 || w == pid as i64) => {
+                p.set_return(pid as u64, code as u64);
+                p.wake();
+                let (parent, nice) = (p.pid, p.nice());
+                self.enqueue(parent, nice);
+                self.processes.retain(|p| p.pid != pid);
+            }
+            Some(_) => {}
+            None => self.processes.retain(|p| p.pid != pid),
+        }
+
+        self.run_next(e)
+    }
+
+    /// Collect the exit code of a child (any child when `pid` is -1), blocking the running process
+    /// until the child exits. Returns -1 if there is no such child.
+    pub fn waitpid(&mut self, pid: i64, e: &mut ExceptionContext) {
+        let index = self.running_index();
+        let parent = self.processes[index].pid;
+        let is_child = |p: &Process| p.parent == parent && (pid == -1 || p.pid as i64 == pid);
+
+        let zombie = self.processes.iter()
+            .position(|p| is_child(p) && matches!(p.state(), ProcessState::Zombie(_)));
+        if let Some(zombie) = zombie {
+            let child = self.processes.remove(zombie);
+            if let ProcessState::Zombie(code) = child.state() {
+                e.gpr.x[0] = child.pid as u64;
+                e.gpr.x[1] = code as u64;
+            }
+            return;
         }
+        if !self.processes.iter().any(is_child) {
+            e.gpr.x[0] = -1i64 as u64;
+            return;
+        }
+
+        self.processes[index].block(ProcessState::Blocked(BlockReason::WaitChild(pid)), e);
+        self.run_next(e)
+    }
+
+    /// Resolve a page fault of the running process, the process is killed if the address is not
+    /// valid.
+    pub fn page_fault(&mut self, addr: usize, e: &ExceptionContext) {
+        if let Err(err) = self.current_process().handle_fault(addr) {
+            debugln!("process {} fault at {:#x} : {}", self.current(), addr, err);
+            self.fault(SIGSEGV, e);
+        }
+    }
 
-        let nb_processes = self.processes.len();
-        match self.processes.get_mut(CNTV_TVAL_EL0.get() as usize % nb_processes) {
-            Some(p ) => p.restore(e.stack_el1),
-            None => wfi()
+    /// Give the FP/SIMD registers to the running process, after saving them for their previous
+    /// owner.
+    pub fn fp_trap(&mut self) {
+        let current = self.current();
+        let fp_owner = self.core().fp_owner;
+        if fp_owner != current {
+            match self.processes.iter_mut().find(|p| p.pid == fp_owner) {
+                Some(owner) => owner.fp.save(),
+                None => {}
+            }
+            self.current_process().fp.restore();
+            self.core().fp_owner = current;
         }
+        fp::enable();
     }
 
+    /// Move the end of the heap of the running process (0 to read it), returns the new end or -1.
+    pub fn brk(&mut self, addr: usize, e: &mut ExceptionContext) {
+        e.gpr.x[0] = match self.current_process().set_brk(addr) {
+            Ok(brk) => brk as u64,
+            Err(_) => -1i64 as u64,
+        };
+    }
 
-    pub fn sleep(&mut self, pid: u16, ms: u64, e: &ExceptionContext) {
-        match self.processes.iter_mut()
-            .find(|p| p.pid == pid) {
-            Some(p) => p.pause(&e.gpr, e.spsr_el1, e.elr_el1, e.stack_el0),
+    /// Map `len` bytes of zeroed memory in the running process, returns the address or -1.
+    pub fn mmap(&mut self, len: usize, e: &mut ExceptionContext) {
+        e.gpr.x[0] = match self.current_process().mmap(len) {
+            Ok(addr) => addr as u64,
+            Err(_) => -1i64 as u64,
+        };
+    }
+
+    /// Unmap memory returned by `mmap`, returns 0 or -1.
+    pub fn munmap(&mut self, addr: usize, len: usize, e: &mut ExceptionContext) {
+        e.gpr.x[0] = match self.current_process().munmap(addr, len) {
+            Ok(()) => 0,
+            Err(_) => -1i64 as u64,
+        };
+    }
+
+    /// Change the nice value of the running process (`pid` 0) or of one of its children, the
+    /// value is clamped to NICE_MIN..=NICE_MAX. Only root can lower a nice value. Returns -1 if
+    /// there is no such process or the change is not allowed.
+    pub fn set_priority(&mut self, pid: u16, nice: i64, e: &mut ExceptionContext) {
+        let current = self.current();
+        let uid = self.current_process().credentials.uid;
+        let target = if pid == 0 { current } else { pid };
+        let nice = nice.clamp(NICE_MIN as i64, NICE_MAX as i64) as i8;
+        match self.processes.iter_mut().find(|p| p.pid == target && (p.pid == current || p.parent == current)) {
+            Some(p) if uid == 0 || nice >= p.nice() => {
+                p.set_nice(nice);
+                // it may be waiting in the queue of any core
+                self.cores.iter_mut().for_each(|c| c.policy.renice(target, nice));
+                e.gpr.x[0] = 0;
+            },
+            _ => e.gpr.x[0] = -1i64 as u64,
+        }
+    }
+
+    /// Block the running process for `ms` milliseconds, the scheduler skips it until then.
+    pub fn sleep(&mut self, ms: u64, e: &ExceptionContext) -> ! {
+        let deadline = TIMER.deadline(Duration::from_millis(ms));
+        let process = self.current_process();
+        let pid = process.pid;
+        process.block(ProcessState::Sleeping(deadline), e);
+
+        let position = self.wakeups.partition_point(|&(d, _)| d <= deadline);
+        self.wakeups.insert(position, (deadline, pid));
+
+        self.run_next(e)
+    }
+
+    /// Copy up to `len` received characters at `buf`, returns how many were copied or EFAULT. The
+    /// running process is blocked until the UART receives something if nothing is buffered.
+    pub fn read(&mut self, buf: usize, len: usize, e: &mut ExceptionContext) {
+        if len == 0 || !self.input.is_empty() {
+            let count = len.min(self.input.len());
+            let chars: Vec<u8> = self.input.range(..count).copied().collect();
+            // the characters stay buffered when the program can't take them
+            e.gpr.x[0] = match self.current_process().copy_to_user(buf, &chars) {
+                Ok(()) => {
+                    self.input.drain(..count);
+                    count as u64
+                },
+                Err(_) => EFAULT as u64,
+            };
+            return;
+        }
+
+        // the syscall is issued again once woken up, to collect the characters
+        e.elr_el1 -= 4;
+        let process = self.current_process();
+        let pid = process.pid;
+        process.block(ProcessState::Blocked(BlockReason::UartRx), e);
+        self.uart_readers.push(pid);
+        self.run_next(e)
+    }
+
+    /// Buffer a character received by the UART and wake up the processes waiting for it.
+    pub fn receive(&mut self, c: u8) {
+        self.input.push_back(c);
+        while let Some(pid) = self.uart_readers.pop() {
+            self.wake_blocked(pid, BlockReason::UartRx);
+        }
+    }
+
+    /// Make `pid` runnable if it is still blocked for `reason`.
+    fn wake_blocked(&mut self, pid: u16, reason: BlockReason) {
+        match self.processes.iter_mut().find(|p| p.pid == pid) {
+            Some(p) if p.state() == ProcessState::Blocked(reason) => {
+                p.wake();
+                let nice = p.nice();
+                self.enqueue(pid, nice);
+            },
             _ => {}
         }
     }
+
+    /// Send `sig` to the process `pid`, it is woken up if it is blocked and the signal has an
+    /// effect. Returns -1 if there is no such process or it belongs to another user.
+    pub fn kill(&mut self, pid: u16, sig: u32, e: &mut ExceptionContext) {
+        let uid = self.current_process().credentials.uid;
+        let target = self.processes.iter_mut()
+            .find(|p| p.pid == pid && !matches!(p.state(), ProcessState::Zombie(_)));
+        let target = match target {
+            Some(p) if Signals::is_valid(sig) && (uid == 0 || p.credentials.uid == uid) => p,
+            _ => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        e.gpr.x[0] = 0;
+        if target.signals.ignores(sig) {
+            return;
+        }
+        target.signals.raise(sig);
+        let mut interrupted_call = None;
+        let woken = match target.state() {
+            ProcessState::Blocked(reason) => {
+                // waitpid and call are interrupted, the other syscalls are issued again
+                match reason {
+                    BlockReason::WaitChild(_) => target.set_return(-1i64 as u64, 0),
+                    BlockReason::IpcReply { call, .. } => {
+                        target.set_return(-1i64 as u64, NO_HANDLE as u64);
+                        interrupted_call = Some(call);
+                    },
+                    _ => {}
+                }
+                true
+            },
+            ProcessState::Sleeping(_) => {
+                self.wakeups.retain(|&(_, sleeper)| sleeper != pid);
+                true
+            },
+            _ => false,
+        };
+        if woken {
+            target.wake();
+            let nice = target.nice();
+            self.enqueue(pid, nice);
+        }
+        if let Some(call) = interrupted_call {
+            self.cancel_calls(pid, Some(call));
+            self.release_endpoints();
+        }
+        if pid == self.current() {
+            unsafe { self.schedule(e) }
+        }
+    }
+
+    /// Signal a fault to the running process, which can't go on with the faulting instruction
+    /// unless it handles the signal.
+    pub fn fault(&mut self, sig: u32, e: &ExceptionContext) -> ! {
+        let signals = &mut self.current_process().signals;
+        if signals.action(sig) == Action::Ignore {
+            signals.set_action(sig, SIG_DFL, 0).expect("fault signals can be caught");
+        }
+        signals.raise(sig);
+        unsafe { self.schedule(e) }
+    }
+
+    /// Set the action of `sig` for the running process, returns the previous handler or -1.
+    pub fn sigaction(&mut self, sig: u32, handler: usize, restorer: usize, e: &mut ExceptionContext) {
+        e.gpr.x[0] = match self.current_process().signals.set_action(sig, handler, restorer) {
+            Ok(previous) => previous as u64,
+            Err(_) => -1i64 as u64,
+        };
+    }
+
+    /// Resume the running process where it was before its signal handler, from the frame at the
+    /// top of its stack.
+    pub fn sigreturn(&mut self, e: &ExceptionContext) -> ! {
+        let process = self.current_process();
+        if let Err(err) = process.leave_handler(e.stack_el0 as usize) {
+            debugln!("process {} killed by sigreturn : {}", process.pid, err);
+            self.exit(128 + SIGSEGV as i32, e);
+        }
+        // the core holds the FP/SIMD registers of the handler, the saved ones are loaded on the
+        // next FP/SIMD instruction
+        let core = self.core();
+        if core.fp_owner == core.current {
+            core.fp_owner = 0;
+        }
+        fp::trap();
+        self.current_process().restore(e.stack_el1)
+    }
+
+    pub fn getpid(&self, e: &mut ExceptionContext) {
+        e.gpr.x[0] = self.current() as u64;
+    }
+
+    pub fn getppid(&mut self, e: &mut ExceptionContext) {
+        e.gpr.x[0] = self.current_process().parent as u64;
+    }
+
+    /// Copy the statistics of the `index`th process at `addr`, returns 0, -1 if there is no such
+    /// process or EFAULT if `addr` is not writable.
+    pub fn proc_info(&mut self, index: usize, addr: usize, e: &mut ExceptionContext) {
+        let info = match self.processes.get(index) {
+            Some(p) => p.info(),
+            None => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let bytes = unsafe {
+            slice::from_raw_parts(&info as *const ProcInfo as *const u8, size_of::<ProcInfo>())
+        };
+        e.gpr.x[0] = match self.current_process().copy_to_user(addr, bytes) {
+            Ok(()) => 0,
+            Err(_) => EFAULT as u64,
+        };
+    }
+
+    /// Create an endpoint, returns the handle to it in the running process.
+    pub fn endpoint(&mut self, e: &mut ExceptionContext) {
+        self.endpoint_id += 1;
+        let id = self.endpoint_id;
+        self.endpoints.push(Endpoint::new(id));
+        e.gpr.x[0] = self.current_process().add_handle(Handle::Endpoint(id)) as u64;
+    }
+
+    /// Queue `len` bytes at `buf` in the endpoint at the handle `index`, the receiver gets the
+    /// capability at the handle `handle` too (NO_HANDLE for none). Returns 0, -1 if the message
+    /// is invalid or the endpoint is full, or EFAULT.
+    pub fn send(&mut self, index: usize, buf: usize, len: usize, handle: i64, e: &mut ExceptionContext) {
+        e.gpr.x[0] = match self.message_data(buf, len) {
+            Ok(data) => match self.send_message(index, data, handle, None) {
+                Ok(()) => 0,
+                Err(_) => -1i64 as u64,
+            },
+            Err(errno) => errno as u64,
+        };
+    }
+
+    /// Copy the next message of the endpoint at the handle `index` at `buf`, truncated to `len`
+    /// bytes. Returns the length copied, the sender PID and the handle given for the capability
+    /// of the message (NO_HANDLE for none), or -1 (EFAULT if `buf` is not writable). The running
+    /// process is blocked until a message comes.
+    pub fn recv(&mut self, index: usize, buf: usize, len: usize, e: &mut ExceptionContext) {
+        let endpoint = match self.endpoint_at(index) {
+            Some(endpoint) => endpoint,
+            None => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let message = match self.endpoints[endpoint].pop() {
+            Some(message) => message,
+            None => {
+                // the syscall is issued again once woken up, to collect the message
+                e.elr_el1 -= 4;
+                let id = self.endpoints[endpoint].id;
+                let process = self.current_process();
+                let pid = process.pid;
+                process.block(ProcessState::Blocked(BlockReason::IpcRecv(id)), e);
+                self.endpoints[endpoint].receivers.push(pid);
+                self.run_next(e)
+            }
+        };
+
+        let count = len.min(message.data.len());
+        let process = self.current_process();
+        if process.copy_to_user(buf, &message.data[..count]).is_err() {
+            if let Some(call) = message.call {
+                self.fail_caller(message.sender, call);
+            }
+            e.gpr.x[0] = EFAULT as u64;
+            return;
+        }
+        let handle = message.handle.map_or(NO_HANDLE, |h| process.add_handle(h) as i64);
+        let previous = message.call.and_then(|call| process.reply_to.replace((message.sender, call)));
+        if let Some((caller, call)) = previous {
+            self.fail_caller(caller, call);
+        }
+        e.gpr.x[0] = count as u64;
+        e.gpr.x[1] = message.sender as u64;
+        e.gpr.x[2] = handle as u64;
+    }
+
+    /// Send a message as `send` does, then block the running process until the receiver replies.
+    /// The reply is copied at `reply_buf`, truncated to `reply_len` bytes. Returns the length
+    /// copied and the handle given for the capability of the reply, or -1 (EFAULT for a buffer
+    /// out of the program memory).
+    pub fn call(&mut self, index: usize, buf: usize, len: usize, reply_buf: usize, reply_len: usize,
+                handle: i64, e: &mut ExceptionContext) {
+        let call = self.call_id + 1;
+        let sent = self.message_data(buf, len)
+            .and_then(|data| self.send_message(index, data, handle, Some(call)).map_err(|_| -1));
+        if let Err(errno) = sent {
+            e.gpr.x[0] = errno as u64;
+            return;
+        }
+        self.call_id = call;
+        let reason = BlockReason::IpcReply { call, buf: reply_buf, len: reply_len };
+        self.current_process().block(ProcessState::Blocked(reason), e);
+        self.run_next(e)
+    }
+
+    /// Answer the last call received by the running process with `len` bytes at `buf` and the
+    /// capability at the handle `handle`. Returns 0, -1 if no caller waits for a reply, or EFAULT.
+    pub fn reply(&mut self, buf: usize, len: usize, handle: i64, e: &mut ExceptionContext) {
+        let (caller, call) = match self.current_process().reply_to.take() {
+            Some(reply_to) => reply_to,
+            None => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let message = self.message_data(buf, len)
+            .and_then(|data| self.message(data, handle, None).map_err(|_| -1));
+        let message = match message {
+            Ok(message) => message,
+            Err(errno) => {
+                self.fail_caller(caller, call);
+                e.gpr.x[0] = errno as u64;
+                return;
+            }
+        };
+        let process = match self.processes.iter_mut().find(|p| p.pid == caller) {
+            Some(p) => p,
+            None => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+        let (reply_buf, reply_len) = match process.state() {
+            ProcessState::Blocked(BlockReason::IpcReply { call: waited, buf, len }) if waited == call => (buf, len),
+            // the call was interrupted meanwhile
+            _ => {
+                e.gpr.x[0] = -1i64 as u64;
+                return;
+            }
+        };
+
+        // a reply buffer the caller can't write only fails the caller
+        let count = reply_len.min(message.data.len());
+        match process.copy_to_user(reply_buf, &message.data[..count]) {
+            Ok(()) => {
+                let handle = message.handle.map_or(NO_HANDLE, |h| process.add_handle(h) as i64);
+                process.set_return(count as u64, handle as u64);
+            },
+            Err(_) => process.set_return(EFAULT as u64, NO_HANDLE as u64),
+        }
+        process.wake();
+        let nice = process.nice();
+        self.enqueue(caller, nice);
+        e.gpr.x[0] = 0;
+    }
+
+    /// Index of the endpoint behind the handle `index` of the running process.
+    fn endpoint_at(&mut self, index: usize) -> Option<usize> {
+        match self.current_process().handle(index) {
+            Some(Handle::Endpoint(id)) => self.endpoints.iter().position(|ep| ep.id == id),
+            _ => None,
+        }
+    }
+
+    /// Copy the `len` bytes of a message at `buf` out of the running process, the error is -1 if
+    /// it is too long or EFAULT.
+    fn message_data(&mut self, buf: usize, len: usize) -> Result<Vec<u8>, i64> {
+        if len > MSG_MAX {
+            return Err(-1);
+        }
+        let mut data = vec![0u8; len];
+        self.current_process().copy_from_user(buf, &mut data).map_err(|_| EFAULT)?;
+        Ok(data)
+    }
+
+    /// Message of the running process carrying `data`, with the capability at the handle `handle`.
+    fn message(&mut self, data: Vec<u8>, handle: i64, call: Option<u32>) -> Result<Message, &'static str> {
+        let process = self.current_process();
+        let handle = match handle {
+            NO_HANDLE => None,
+            index => Some(process.handle(index as usize).ok_or("no such handle")?),
+        };
+        Ok(Message { sender: process.pid, data, handle, call })
+    }
+
+    /// Queue a message of the running process in the endpoint at the handle `index`, and wake
+    /// up the processes waiting for it.
+    fn send_message(&mut self, index: usize, data: Vec<u8>, handle: i64, call: Option<u32>) -> Result<(), &'static str> {
+        let endpoint = self.endpoint_at(index).ok_or("no such endpoint")?;
+        let message = self.message(data, handle, call)?;
+        let endpoint = &mut self.endpoints[endpoint];
+        endpoint.push(message).map_err(|_| "endpoint is full")?;
+        let id = endpoint.id;
+        let mut receivers = Vec::new();
+        while let Some(pid) = endpoint.receivers.pop() {
+            receivers.push(pid);
+        }
+        for pid in receivers {
+            self.wake_blocked(pid, BlockReason::IpcRecv(id));
+        }
+        Ok(())
+    }
+
+    /// Wake up `pid` with -1 if it is still blocked in the call `call`, which won't be answered.
+    fn fail_caller(&mut self, pid: u16, call: u32) {
+        if let Some(p) = self.processes.iter_mut().find(|p| p.pid == pid) {
+            if matches!(p.state(), ProcessState::Blocked(BlockReason::IpcReply { call: waited, .. }) if waited == call) {
+                p.set_return(-1i64 as u64, NO_HANDLE as u64);
+                p.wake();
+                let nice = p.nice();
+                self.enqueue(pid, nice);
+            }
+        }
+    }
+
+    /// Forget the calls of `caller` (only `call` if given), nobody waits for their reply anymore :
+    /// their queued messages are dropped and the receivers can't reply to them.
+    fn cancel_calls(&mut self, caller: u16, call: Option<u32>) {
+        let matches = |sender: u16, id: u32| sender == caller && call.map_or(true, |call| call == id);
+        self.endpoints.iter_mut().for_each(|ep| ep.cancel_calls(matches));
+        self.processes.iter_mut()
+            .filter(|p| p.reply_to.is_some_and(|(sender, id)| matches(sender, id)))
+            .for_each(|p| p.reply_to = None);
+    }
+
+    /// Drop the endpoints no process nor queued message has a handle to, the processes which
+    /// called them are failed.
+    fn release_endpoints(&mut self) {
+        let is_held = |id: u32| {
+            let handle = Some(Handle::Endpoint(id));
+            self.processes.iter().any(|p| p.handles.contains(&handle))
+                || self.endpoints.iter().flat_map(|ep| ep.messages()).any(|m| m.handle == handle)
+        };
+        let released: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&i| !is_held(self.endpoints[i].id))
+            .collect();
+        for index in released.into_iter().rev() {
+            let mut endpoint = self.endpoints.remove(index);
+            for message in endpoint.drain() {
+                if let Some(call) = message.call {
+                    self.fail_caller(message.sender, call);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,149 @@
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+use shared::memory::mapping::{AttributeFields, MemAttributes, AccessPermissions};
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LSB: u8 = 1;
+const ELF_TYPE_EXEC: u16 = 2;
+const ELF_MACHINE_AARCH64: u16 = 183;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_GNU_STACK: u32 = 0x6474_E551;
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+
+/// ELF64 file header, as per the System V ABI.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct FileHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    ph_offset: u64,
+    sh_offset: u64,
+    flags: u32,
+    eh_size: u16,
+    ph_entry_size: u16,
+    ph_count: u16,
+    sh_entry_size: u16,
+    sh_count: u16,
+    sh_str_index: u16,
+}
+
+/// ELF64 program header, describing one segment of the executable.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    file_size: u64,
+    mem_size: u64,
+    align: u64,
+}
+
+/// A statically linked AArch64 executable, borrowed from its raw bytes.
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    header: FileHeader,
+}
+
+/// A loadable segment : `data` must be copied at `vaddr` and the rest of `mem_size` zeroed (.bss).
+#[derive(Debug)]
+pub struct Segment<'a> {
+    pub vaddr: usize,
+    pub mem_size: usize,
+    pub data: &'a [u8],
+    pub flags: u32,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Elf<'a>, &'static str> {
+        if bytes.len() < size_of::<FileHeader>() {
+            return Err("ELF file is too small");
+        }
+        let header = unsafe { read_unaligned(bytes.as_ptr() as *const FileHeader) };
+
+        if header.ident[0..4] != ELF_MAGIC {
+            return Err("not an ELF file");
+        }
+        if header.ident[4] != ELF_CLASS_64 || header.ident[5] != ELF_DATA_LSB {
+            return Err("ELF file is not a 64 bits little endian file");
+        }
+        if header.kind != ELF_TYPE_EXEC || header.machine != ELF_MACHINE_AARCH64 {
+            return Err("ELF file is not an AArch64 executable");
+        }
+        if header.ph_entry_size as usize != size_of::<ProgramHeader>() {
+            return Err("ELF program header has an unexpected size");
+        }
+        let ph_end = (header.ph_count as usize).checked_mul(size_of::<ProgramHeader>())
+            .and_then(|size| size.checked_add(header.ph_offset as usize));
+        if ph_end.map_or(true, |end| end > bytes.len()) {
+            return Err("ELF program headers are out of the file");
+        }
+
+        let elf = Elf { bytes, header };
+        for ph in elf.program_headers().filter(|ph| ph.kind == PT_LOAD) {
+            let end = ph.offset.checked_add(ph.file_size);
+            if ph.file_size > ph.mem_size || end.map_or(true, |end| end > bytes.len() as u64) {
+                return Err("ELF segment is out of the file");
+            }
+            if ph.vaddr.checked_add(ph.mem_size).is_none() {
+                return Err("ELF segment is out of the address space");
+            }
+        }
+        let entry = elf.header.entry;
+        let executable = elf.program_headers()
+            .any(|ph| ph.kind == PT_LOAD && ph.flags & PF_X != 0 && (ph.vaddr..ph.vaddr + ph.mem_size).contains(&entry));
+        if !executable {
+            return Err("ELF entry point is not in an executable segment");
+        }
+        Ok(elf)
+    }
+
+    /// Address of the first instruction to run.
+    pub fn entry(&self) -> usize {
+        self.header.entry as usize
+    }
+
+    fn program_headers(&self) -> impl Iterator<Item=ProgramHeader> + '_ {
+        (0..self.header.ph_count as usize).map(move |i| {
+            let offset = self.header.ph_offset as usize + i * size_of::<ProgramHeader>();
+            unsafe { read_unaligned(self.bytes.as_ptr().add(offset) as *const ProgramHeader) }
+        })
+    }
+
+    /// Stack size requested with the PT_GNU_STACK header (`-z stack-size=`), if any.
+    pub fn stack_size(&self) -> Option<usize> {
+        self.program_headers()
+            .find(|ph| ph.kind == PT_GNU_STACK && ph.mem_size > 0)
+            .map(|ph| ph.mem_size as usize)
+    }
+
+    /// Iterate over the PT_LOAD segments.
+    pub fn segments(&self) -> impl Iterator<Item=Segment<'a>> + '_ {
+        self.program_headers()
+            .filter(|ph| ph.kind == PT_LOAD && ph.mem_size > 0)
+            .map(move |ph| Segment {
+                vaddr: ph.vaddr as usize,
+                mem_size: ph.mem_size as usize,
+                data: &self.bytes[ph.offset as usize..(ph.offset + ph.file_size) as usize],
+                flags: ph.flags,
+            })
+    }
+}
+
+/// MMU attributes matching the R/W/X flags of a segment.
+pub fn attribute_fields(flags: u32) -> AttributeFields {
+    AttributeFields {
+        mem_attributes: MemAttributes::CacheableDRAM,
+        acc_perms: if flags & PF_W != 0 { AccessPermissions::ReadWriteUser } else { AccessPermissions::ReadOnlyUser },
+        execute_never: flags & PF_X == 0,
+    }
+}
@@ -0,0 +1,49 @@
+use aarch64_cpu::registers::{CPACR_EL1, Writeable};
+use aarch64_cpu::asm::barrier;
+
+extern "C" {
+    fn __save_fp(state: *mut FpState);
+    fn __restore_fp(state: *const FpState);
+}
+
+/// FP/SIMD registers of a process, they are only saved and restored when another process uses
+/// them, the first FP/SIMD instruction of a process traps (EC 0x07) while it does not own them.
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone)]
+pub struct FpState {
+    q: [u128; 32],
+    fpcr: u64,
+    fpsr: u64,
+}
+
+impl FpState {
+    pub const fn new() -> Self {
+        FpState {
+            q: [0; 32],
+            fpcr: 0,
+            fpsr: 0,
+        }
+    }
+
+    /// Copy the FP/SIMD registers in the state.
+    pub fn save(&mut self) {
+        unsafe { __save_fp(self) };
+    }
+
+    /// Load the FP/SIMD registers from the state.
+    pub fn restore(&self) {
+        unsafe { __restore_fp(self) };
+    }
+}
+
+/// Let the running process use the FP/SIMD registers, the kernel always can.
+pub fn enable() {
+    CPACR_EL1.write(CPACR_EL1::FPEN::TrapNothing);
+    barrier::isb(barrier::SY);
+}
+
+/// Trap the next FP/SIMD instruction of the running process.
+pub fn trap() {
+    CPACR_EL1.write(CPACR_EL1::FPEN::TrapEl0);
+    barrier::isb(barrier::SY);
+}
@@ -0,0 +1,22 @@
+use core::arch::asm;
+use aarch64_cpu::asm::wfi;
+use aarch64_cpu::registers::{ELR_EL1, SPSR_EL1, SP, Writeable};
+use crate::global::SCHEDULER;
+
+/// Kernel task running when no process is runnable, it sleeps until the next interruption.
+pub fn idle() -> ! {
+    loop {
+        wfi();
+    }
+}
+
+/// Switch to the idle task on the kernel `stack`, with the IRQs unmasked so the interruptions
+/// bring the scheduler back. The scheduler is released, as on the return to a process.
+pub fn run(stack: u64) -> ! {
+    SPSR_EL1.write(SPSR_EL1::M::EL1h + SPSR_EL1::D::Masked + SPSR_EL1::A::Masked
+        + SPSR_EL1::I::Unmasked + SPSR_EL1::F::Masked);
+    ELR_EL1.set(idle as *const () as u64);
+    SP.set(stack);
+    unsafe { SCHEDULER.force_unlock() };
+    unsafe { asm!("eret", options(noreturn)) }
+}
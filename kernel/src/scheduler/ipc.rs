@@ -0,0 +1,64 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use mmio::syscall::MSG_MAX;
+use crate::scheduler::process::Handle;
+use crate::scheduler::wait::WaitQueue;
+
+/// Messages an endpoint keeps until they are received, `send` fails past them.
+pub const MAX_QUEUED: usize = 16;
+
+/// Message copied out of the sender, waiting in an endpoint until it is received.
+pub struct Message {
+    pub sender: u16,
+    pub data: Vec<u8>,
+    /// capability given to the receiver, which gets its own handle to the same object
+    pub handle: Option<Handle>,
+    /// id of the call the sender is blocked in until the receiver replies, None for `send`
+    pub call: Option<u32>,
+}
+
+/// Kernel side of a channel, any process with a handle to it can send and receive.
+pub struct Endpoint {
+    pub id: u32,
+    messages: VecDeque<Message>,
+    /// processes blocked in `recv` until a message comes
+    pub receivers: WaitQueue,
+}
+
+impl Endpoint {
+    pub fn new(id: u32) -> Self {
+        Endpoint {
+            id,
+            messages: VecDeque::new(),
+            receivers: WaitQueue::new(),
+        }
+    }
+
+    /// Queue `message`, which is given back if it is too long or if the endpoint is full.
+    pub fn push(&mut self, message: Message) -> Result<(), Message> {
+        if message.data.len() > MSG_MAX || self.messages.len() >= MAX_QUEUED {
+            return Err(message);
+        }
+        self.messages.push_back(message);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+
+    /// Queued messages, which keep their endpoint alive with the capabilities they carry.
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    /// Drop the messages of the calls `matches` selects, as nobody waits for their reply anymore.
+    pub fn cancel_calls(&mut self, matches: impl Fn(u16, u32) -> bool) {
+        self.messages.retain(|m| !m.call.is_some_and(|call| matches(m.sender, call)));
+    }
+
+    /// Empty the endpoint, once nothing can receive from it anymore.
+    pub fn drain(&mut self) -> impl Iterator<Item = Message> + '_ {
+        self.messages.drain(..)
+    }
+}
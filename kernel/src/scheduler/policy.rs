@@ -0,0 +1,149 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+/// Decide which runnable process gets the next time slice.
+pub trait SchedulingPolicy {
+    /// A process became runnable, it stays queued until it is picked by `next`.
+    fn enqueue(&mut self, pid: u16, nice: i8);
+
+    /// Pick the process to run for the next time slice.
+    fn next(&mut self) -> Option<u16>;
+
+    /// The nice value of `pid` changed, it is taken into account if the process is queued.
+    fn renice(&mut self, pid: u16, nice: i8);
+
+    /// Number of queued processes.
+    fn len(&self) -> usize;
+}
+
+/// Every process runs in turn, in the order they became runnable.
+#[derive(Default)]
+pub struct RoundRobin {
+    queue: VecDeque<u16>,
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        RoundRobin {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn enqueue(&mut self, pid: u16, _nice: i8) {
+        self.queue.push_back(pid);
+    }
+
+    fn next(&mut self) -> Option<u16> {
+        self.queue.pop_front()
+    }
+
+    fn renice(&mut self, _pid: u16, _nice: i8) {}
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+struct Entry {
+    pid: u16,
+    nice: i8,
+    /// time slices spent waiting in the queue
+    age: u32,
+}
+
+/// The process with the lowest nice value runs first. Waiting processes age by one nice level
+/// per time slice, so the lower priorities are not starved.
+pub struct Priority {
+    queue: Vec<Entry>,
+}
+
+impl Priority {
+    pub const fn new() -> Self {
+        Priority {
+            queue: Vec::new(),
+        }
+    }
+}
+
+impl SchedulingPolicy for Priority {
+    fn enqueue(&mut self, pid: u16, nice: i8) {
+        self.queue.push(Entry { pid, nice, age: 0 });
+    }
+
+    fn next(&mut self) -> Option<u16> {
+        // the oldest entry wins the ties, as the queue is in arrival order
+        let index = self.queue.iter()
+            .enumerate()
+            .max_by_key(|(i, entry)| (entry.age as i64 - entry.nice as i64, -(*i as i64)))
+            .map(|(i, _)| i)?;
+        let picked = self.queue.remove(index);
+        self.queue.iter_mut().for_each(|entry| entry.age += 1);
+        Some(picked.pid)
+    }
+
+    fn renice(&mut self, pid: u16, nice: i8) {
+        // the entry keeps its age, only its priority changes
+        self.queue.iter_mut()
+            .filter(|entry| entry.pid == pid)
+            .for_each(|entry| entry.nice = nice);
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Available scheduling policies.
+pub enum Policy {
+    RoundRobin(RoundRobin),
+    Priority(Priority),
+}
+
+impl Policy {
+    /// Policy of the cores, the priorities unless the kernel is built with `round-robin`.
+    pub const fn kernel() -> Self {
+        if cfg!(feature = "round-robin") {
+            Policy::RoundRobin(RoundRobin::new())
+        } else {
+            Policy::Priority(Priority::new())
+        }
+    }
+}
+
+impl From<RoundRobin> for Policy {
+    fn from(instance: RoundRobin) -> Self {
+        Policy::RoundRobin(instance)
+    }
+}
+
+impl From<Priority> for Policy {
+    fn from(instance: Priority) -> Self {
+        Policy::Priority(instance)
+    }
+}
+
+impl Deref for Policy {
+    type Target = dyn SchedulingPolicy;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Policy::RoundRobin(i) => i,
+            Policy::Priority(i) => i,
+        }
+    }
+}
+
+impl DerefMut for Policy {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Policy::RoundRobin(i) => i,
+            Policy::Priority(i) => i,
+        }
+    }
+}
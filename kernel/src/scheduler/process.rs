@@ -1,18 +1,26 @@
 global_asm!(include_str!("context.S"));use alloc::vec::Vec;
-use core::borrow::Borrow;
 
 use aarch64_cpu::registers::{ELR_EL1, SP_EL0, SPSR_EL1, SP, Writeable};
 
-use shared::exceptions::handlers::GPR;
+use shared::exceptions::handlers::{ExceptionContext, GPR};
 
-use shared::memory::mmu::{ArchTranslationTable, setup_dyn_user_tables, switch_user_tables};
-use crate::scheduler::PROG_START;
-use crate::scheduler::process::ProcessState::{Sleep, Running};
-use crate::global::{SCHEDULER};
+use shared::memory::mmu::{TranslationGranule, UserGranule, VA_BITS, switch_user_tables, memory_flush};
+use shared::memory::walker::PageTable;
+use crate::scheduler::{PROG_START, PROG_END, STACK_TOP, DEFAULT_STACK_SIZE, MAX_STACK_SIZE, HEAP_START, HEAP_END, MMAP_START, MMAP_END};
+use crate::scheduler::elf::{self, Elf};
+use crate::scheduler::fp::FpState;
+use crate::scheduler::signal::Signals;
+use mmio::syscall::{ProcInfo, ProcState};
+use crate::memory::phys_to_virt;
+use crate::scheduler::process::ProcessState::{Ready, Running, Zombie};
+use crate::global::{SCHEDULER, FRAMES, TIMER, INITRAMFS};
 use core::fmt::{Debug, Formatter};
 use core::{fmt};
 use core::arch::global_asm;
-use shared::memory::mapping::Descriptor;
+use shared::memory::mapping::{Descriptor, AttributeFields, MemAttributes, AccessPermissions};
+use core::ops::RangeInclusive;
+use core::{ptr, slice};
+use core::mem::size_of;
 
 extern "C" {
     fn __restore_and_eret(regs: usize) -> !;
@@ -44,32 +52,156 @@ impl Default for ProcessContext {
             regs: Default::default(),
             state: 0,
             eret_addr: PROG_START as u64,
-            stack: 0x0040_0000,
+            stack: STACK_TOP as u64,
         }
     }
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum ProcessState {
-    NotRunnable,
+    /// Waiting in the run queue for a time slice
+    Ready,
     Running,
-    Sleep,
+    /// Waiting for an event, a driver or another process wakes it up
+    Blocked(BlockReason),
+    /// Sleeping until the deadline, in ticks of the physical counter
+    Sleeping(u64),
+    /// Exited, until the parent collects the exit code
+    Zombie(i32),
 }
 
+/// Event a blocked process waits for.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum BlockReason {
+    /// A child to exit (-1 for any child)
+    WaitChild(i64),
+    /// Characters received by the UART
+    UartRx,
+    /// A message on the endpoint
+    IpcRecv(u32),
+    /// The reply to the call `call`, copied at `buf` up to `len` bytes
+    IpcReply { call: u32, buf: usize, len: usize },
+}
+
+
+/// Identity the process acts with, inherited by its children.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Kernel objects a process refers to by their index in its handles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Handle {
+    Console,
+    /// IPC endpoint, by its id
+    Endpoint(u32),
+}
+
+/// Time used by a process, in ticks of the physical counter.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Accounting {
+    pub started: u64,
+    pub cpu_time: u64,
+    /// times the process got the CPU
+    pub switches: u64,
+    last_run: u64,
+}
 
-#[derive(Copy, Clone)]
+/// Control block of a process, only reachable by the kernel.
 pub struct Process {
-    pub tlb: ArchTranslationTable,
+    /// user tables, only the tables leading to mapped pages are allocated, from the frames
+    pub tlb: PageTable<UserGranule>,
     pub pid: u16,
+    /// 0 when the parent is the kernel
+    pub parent: u16,
+    pub credentials: Credentials,
+    /// freed handles stay as None, so the other indexes don't change
+    pub handles: Vec<Option<Handle>>,
+    /// process blocked in `call` until this one replies to the message it received, with the id
+    /// of the call
+    pub reply_to: Option<(u16, u32)>,
+    pub accounting: Accounting,
+    /// only up to date when the process does not own the FP/SIMD registers
+    pub fp: FpState,
+    pub signals: Signals,
     state: ProcessState,
+    /// from -20 (highest priority) to 19
+    nice: i8,
     context: ProcessContext,
+    /// user pages of the program, with the frames backing them
+    pages: Vec<Page>,
+    /// user memory only backed by frames on the first access
+    lazy_regions: Vec<LazyRegion>,
+    /// end of the heap, which starts at HEAP_START and is backed like the lazy regions
+    brk: usize,
+    /// the stack ends at STACK_TOP, the page below it is the guard page
+    stack_size: usize,
+}
+
+/// A user page and the physical frame backing it.
+#[derive(Debug, Copy, Clone)]
+struct Page {
+    vaddr: usize,
+    frame: usize,
+    attribute_fields: AttributeFields,
+    /// the frame is shared with another process, it is mapped read only until the first write
+    cow: bool,
+}
+
+impl Page {
+    /// Attributes the page is mapped with, the shared pages are read only.
+    fn mapped_attributes(&self) -> AttributeFields {
+        let mut attribute_fields = self.attribute_fields;
+        if self.cow {
+            attribute_fields.acc_perms = AccessPermissions::ReadOnlyUser;
+        }
+        attribute_fields
+    }
+}
+
+/// User memory mapped page by page, when the program faults on it.
+#[derive(Debug, Clone)]
+struct LazyRegion {
+    range: RangeInclusive<usize>,
+    attribute_fields: AttributeFields,
 }
 
+/// What a signal handler may change, saved on the user stack until `sigreturn`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct SignalFrame {
+    context: ProcessContext,
+    fp: FpState,
+}
+
+/// Access to memory the program can't reach, reported to it as EFAULT.
+#[derive(Debug, Copy, Clone)]
+pub struct Fault;
+
+impl From<Fault> for &'static str {
+    fn from(_: Fault) -> Self {
+        "bad address in the program memory"
+    }
+}
+
+/// Permissions of the stack, heap and mmap memory.
+const USER_DATA: AttributeFields = AttributeFields {
+    mem_attributes: MemAttributes::CacheableDRAM,
+    acc_perms: AccessPermissions::ReadWriteUser,
+    execute_never: true,
+};
+
 impl Debug for Process {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let string = format!("Process with mmu {:x} - ", self.tlb.phys_base_addr());
         f.debug_tuple(string.as_str())
             .field(&self.pid)
+            .field(&self.parent)
+            .field(&self.credentials)
+            .field(&self.handles)
+            .field(&self.accounting)
             .field(&self.state)
             .field(&self.context)
             .finish()
@@ -77,19 +209,500 @@ impl Debug for Process {
 }
 
 impl Process {
-    pub fn new(pid: u16) -> Self {
-        Process {
-            tlb: ArchTranslationTable::new(),
+    pub fn new(pid: u16, parent: u16) -> Result<Self, &'static str> {
+        Ok(Process {
+            tlb: PageTable::new(&mut *FRAMES.lock(), VA_BITS)?,
             pid,
-            state: Sleep,
+            parent,
+            credentials: Credentials { uid: 0, gid: 0 },
+            handles: vec![Some(Handle::Console)],
+            reply_to: None,
+            accounting: Accounting {
+                started: TIMER.now(),
+                ..Default::default()
+            },
+            fp: FpState::new(),
+            signals: Signals::new(),
+            state: Ready,
+            nice: 0,
             context: Default::default(),
+            pages: Vec::new(),
+            lazy_regions: Vec::new(),
+            brk: HEAP_START,
+            stack_size: DEFAULT_STACK_SIZE,
+        })
+    }
+
+    pub fn init_local_tlb(&mut self, descriptors: &[Descriptor]) -> Result<(), &'static str> {
+        self.tlb.map_descriptors(&mut *FRAMES.lock(), descriptors)?;
+        memory_flush();
+        Ok(())
+    }
+
+    /// Drop every mapping and give back the memory, the process has to be set up again with
+    /// `init_local_tlb`.
+    pub fn clear_local_tlb(&mut self) {
+        self.tlb.clear(&mut *FRAMES.lock());
+        self.release_memory();
+        memory_flush();
+        self.lazy_regions.clear();
+        self.brk = HEAP_START;
+    }
+
+    /// Back `range` with newly allocated, zeroed frames.
+    fn map_user(&mut self, range: RangeInclusive<usize>, attribute_fields: AttributeFields) -> Result<(), &'static str> {
+        for vaddr in range.step_by(UserGranule::SIZE) {
+            let frame = FRAMES.lock().alloc().ok_or("no more user memory for the program")?;
+            unsafe { ptr::write_bytes(phys_to_virt(frame) as *mut u8, 0, UserGranule::SIZE); }
+            if let Err(err) = self.map_frame(vaddr, frame, attribute_fields) {
+                FRAMES.lock().free(frame);
+                return Err(err);
+            }
         }
+        Ok(())
     }
 
-    pub fn init_local_tlb(&mut self, descriptors: &Vec<Descriptor>) {
-        let desc_iter = descriptors.iter();
-        setup_dyn_user_tables(&desc_iter, &mut self.tlb, self.pid);
-        print!("MMU Program mapping : \n{}", self.tlb);
+    fn map_frame(&mut self, vaddr: usize, frame: usize, attribute_fields: AttributeFields) -> Result<(), &'static str> {
+        let page = Page { vaddr, frame, attribute_fields, cow: false };
+        self.map_page(&page)?;
+        self.pages.push(page);
+        Ok(())
+    }
+
+    fn map_page(&mut self, page: &Page) -> Result<(), &'static str> {
+        self.tlb.map(&mut *FRAMES.lock(), page.vaddr, page.frame, &page.mapped_attributes())
+    }
+
+    /// Update the permissions of a mapped page, which keeps its frame.
+    fn protect_page(&mut self, page: &Page) -> Result<(), &'static str> {
+        self.tlb.protect(&*FRAMES.lock(), page.vaddr, &page.mapped_attributes())
+    }
+
+    /// Copy `data` at the user address `vaddr`, through the kernel mapping of the frames.
+    fn write_user(&self, vaddr: usize, data: &[u8]) {
+        let mut written = 0;
+        while written < data.len() {
+            let addr = vaddr + written;
+            let page = self.pages.iter()
+                .find(|p| p.vaddr == addr & UserGranule::ALIGN)
+                .expect("user address is not mapped");
+            let len = (UserGranule::SIZE - (addr & UserGranule::MASK)).min(data.len() - written);
+            unsafe {
+                ptr::copy(data[written..].as_ptr(), phys_to_virt(page.frame + (addr & UserGranule::MASK)) as *mut u8, len);
+            }
+            written += len;
+        }
+    }
+
+    /// Physical address of the user byte at `addr`, as translated by the tables of the process.
+    /// Its page is faulted in (and copied if it is shared and `write`) as if the program accessed
+    /// it.
+    ///
+    /// The copy helpers below only reach the user memory through its physical address, after
+    /// this check. Without PAN (the Cortex-A53 has none), they are the only thing keeping the
+    /// kernel from following a user pointer.
+    fn user_frame(&mut self, addr: usize, write: bool) -> Result<usize, &'static str> {
+        loop {
+            match self.pages.iter().find(|p| p.vaddr == addr & UserGranule::ALIGN) {
+                Some(page) if !page.attribute_fields.is_user() => return Err("page is not user memory"),
+                Some(page) if write && !page.attribute_fields.is_writable() => return Err("page is read only"),
+                Some(page) if !(write && page.cow) => {
+                    return self.tlb.translate(&*FRAMES.lock(), addr).ok_or("page is not mapped");
+                },
+                _ => self.handle_fault(addr)?,
+            }
+        }
+    }
+
+    /// `len` bytes from `vaddr` fit in the user address space.
+    fn check_user_range(vaddr: usize, len: usize) -> Result<(), Fault> {
+        match vaddr.checked_add(len) {
+            Some(end) if end <= 1 << VA_BITS => Ok(()),
+            _ => Err(Fault),
+        }
+    }
+
+    /// Copy `data` to the user memory at `vaddr`, with the permissions of the program.
+    pub fn copy_to_user(&mut self, vaddr: usize, data: &[u8]) -> Result<(), Fault> {
+        Self::check_user_range(vaddr, data.len())?;
+        let mut written = 0;
+        while written < data.len() {
+            let addr = vaddr + written;
+            let frame = self.user_frame(addr, true).map_err(|_| Fault)?;
+            let len = (UserGranule::SIZE - (addr & UserGranule::MASK)).min(data.len() - written);
+            unsafe {
+                ptr::copy(data[written..].as_ptr(), phys_to_virt(frame) as *mut u8, len);
+            }
+            written += len;
+        }
+        Ok(())
+    }
+
+    /// Copy the user memory at `vaddr` to `buf`, with the permissions of the program.
+    pub fn copy_from_user(&mut self, vaddr: usize, buf: &mut [u8]) -> Result<(), Fault> {
+        Self::check_user_range(vaddr, buf.len())?;
+        let mut read = 0;
+        while read < buf.len() {
+            let addr = vaddr + read;
+            let frame = self.user_frame(addr, false).map_err(|_| Fault)?;
+            let len = (UserGranule::SIZE - (addr & UserGranule::MASK)).min(buf.len() - read);
+            unsafe {
+                ptr::copy(phys_to_virt(frame) as *const u8, buf[read..].as_mut_ptr(), len);
+            }
+            read += len;
+        }
+        Ok(())
+    }
+
+    /// Copy the user string at `vaddr` up to its NUL byte, or `max` bytes, without the NUL.
+    pub fn strncpy_from_user(&mut self, vaddr: usize, max: usize) -> Result<Vec<u8>, Fault> {
+        let mut string = Vec::new();
+        while string.len() < max {
+            let addr = vaddr + string.len();
+            Self::check_user_range(addr, 1)?;
+            let frame = self.user_frame(addr, false).map_err(|_| Fault)?;
+            let len = (UserGranule::SIZE - (addr & UserGranule::MASK)).min(max - string.len());
+            let bytes = unsafe { slice::from_raw_parts(phys_to_virt(frame) as *const u8, len) };
+            match bytes.iter().position(|&b| b == 0) {
+                Some(end) => {
+                    string.extend_from_slice(&bytes[..end]);
+                    break;
+                },
+                None => string.extend_from_slice(bytes),
+            }
+        }
+        Ok(string)
+    }
+
+    /// Unmap the pages in `range` and give their frames back.
+    fn unmap_user(&mut self, range: RangeInclusive<usize>) {
+        let tlb = &mut self.tlb;
+        self.pages.retain(|page| {
+            let unmapped = range.contains(&page.vaddr);
+            if unmapped {
+                tlb.unmap(&mut *FRAMES.lock(), page.vaddr).expect("user page is not mapped");
+                FRAMES.lock().free(page.frame);
+            }
+            !unmapped
+        });
+        memory_flush();
+    }
+
+    /// Give the frames of the process back to the allocator.
+    fn release_memory(&mut self) {
+        for page in self.pages.drain(..) {
+            FRAMES.lock().free(page.frame);
+        }
+    }
+
+    /// Copy a shared page written to, or back the page of `addr` with a frame if it belongs to a
+    /// lazy region. Any other fault is an invalid access.
+    pub fn handle_fault(&mut self, addr: usize) -> Result<(), &'static str> {
+        let page = addr & UserGranule::ALIGN;
+        let stack_bottom = STACK_TOP - self.stack_size;
+        if (stack_bottom - UserGranule::SIZE..stack_bottom).contains(&addr) {
+            return Err("stack overflow");
+        }
+        if let Some(index) = self.pages.iter().position(|p| p.vaddr == page) {
+            return if self.pages[index].cow {
+                self.copy_on_write(index)
+            } else {
+                Err("access not allowed on the page")
+            };
+        }
+        let attribute_fields = if (HEAP_START..self.brk).contains(&addr) {
+            USER_DATA
+        } else {
+            self.lazy_regions.iter()
+                .find(|r| r.range.contains(&addr))
+                .ok_or("address out of the program memory")?
+                .attribute_fields
+        };
+        self.map_user(RangeInclusive::new(page, page + UserGranule::MASK), attribute_fields)?;
+        memory_flush();
+        Ok(())
+    }
+
+    /// Give a shared page its own frame, unless the other processes already dropped it, and map
+    /// it writable again.
+    fn copy_on_write(&mut self, index: usize) -> Result<(), &'static str> {
+        let mut page = self.pages[index];
+        page.cow = false;
+        if FRAMES.lock().references(page.frame) > 1 {
+            let frame = FRAMES.lock().alloc().ok_or("no more user memory for the program")?;
+            unsafe {
+                ptr::copy(phys_to_virt(page.frame) as *const u8, phys_to_virt(frame) as *mut u8, UserGranule::SIZE);
+            }
+            FRAMES.lock().free(page.frame);
+            page.frame = frame;
+            self.map_page(&page)?;
+        } else {
+            // the other processes are done with the frame, it only becomes writable again
+            self.protect_page(&page)?;
+        }
+        self.pages[index] = page;
+        memory_flush();
+        Ok(())
+    }
+
+    /// Map the PT_LOAD segments of the program with their own permissions, copy their content,
+    /// set up the stack and reset the context to the entry point of the program.
+    pub fn load(&mut self, elf: &Elf) -> Result<(), &'static str> {
+        // pages shared by several segments get the permissions of all of them
+        let mut pages: Vec<(usize, u32)> = Vec::new();
+        for segment in elf.segments() {
+            if segment.vaddr < PROG_START || segment.vaddr + segment.mem_size > PROG_END {
+                return Err("ELF segment is out of the program memory");
+            }
+            let first_page = segment.vaddr & UserGranule::ALIGN;
+            for page in (first_page..segment.vaddr + segment.mem_size).step_by(UserGranule::SIZE) {
+                match pages.iter_mut().find(|p| p.0 == page) {
+                    Some(p) => p.1 |= segment.flags,
+                    None => pages.push((page, segment.flags)),
+                }
+            }
+        }
+
+        for (page, flags) in pages.iter() {
+            self.map_user(RangeInclusive::new(*page, page + UserGranule::MASK), elf::attribute_fields(*flags))?;
+        }
+        // the end of the segment (.bss) is already zeroed with the page
+        for segment in elf.segments() {
+            self.write_user(segment.vaddr, segment.data);
+        }
+
+        // the stack is only backed where the program reaches
+        let stack_size = elf.stack_size().unwrap_or(DEFAULT_STACK_SIZE);
+        self.stack_size = ((stack_size + UserGranule::MASK) & UserGranule::ALIGN).min(MAX_STACK_SIZE);
+        self.lazy_regions.push(LazyRegion {
+            range: RangeInclusive::new(STACK_TOP - self.stack_size, STACK_TOP - 1),
+            attribute_fields: USER_DATA,
+        });
+        self.brk = HEAP_START;
+        memory_flush();
+
+        self.context = ProcessContext {
+            eret_addr: elf.entry() as u64,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    /// Give the process the memory of `parent`, mapped the same way. The user pages are shared,
+    /// the writable ones are copied on the first write of either process.
+    pub fn copy_memory(&mut self, parent: &mut Process) -> Result<(), &'static str> {
+        for index in 0..parent.pages.len() {
+            let mut page = parent.pages[index];
+            match page.attribute_fields.acc_perms {
+                AccessPermissions::ReadOnlyUser => FRAMES.lock().share(page.frame),
+                AccessPermissions::ReadWriteUser => {
+                    FRAMES.lock().share(page.frame);
+                    page.cow = true;
+                    if !parent.pages[index].cow {
+                        parent.protect_page(&page)?;
+                        parent.pages[index] = page;
+                    }
+                },
+                // the kernel writes the kernel pages through its own mapping, they can't be shared
+                _ => {
+                    let frame = FRAMES.lock().alloc().ok_or("no more user memory for the program")?;
+                    unsafe {
+                        ptr::copy(phys_to_virt(page.frame) as *const u8, phys_to_virt(frame) as *mut u8, UserGranule::SIZE);
+                    }
+                    page.frame = frame;
+                },
+            }
+            // pushed first so the frame is given back with the child if the mapping fails
+            self.pages.push(page);
+            self.map_page(&page)?;
+        }
+        self.lazy_regions = parent.lazy_regions.clone();
+        self.brk = parent.brk;
+        self.stack_size = parent.stack_size;
+        memory_flush();
+        Ok(())
+    }
+
+    /// Move the end of the heap (0 to read it), the pages above the new end are given back.
+    pub fn set_brk(&mut self, brk: usize) -> Result<usize, &'static str> {
+        if brk == 0 {
+            return Ok(self.brk);
+        }
+        if brk < HEAP_START || brk > HEAP_END + 1 {
+            return Err("heap out of its memory");
+        }
+        let first_unused = (brk + UserGranule::MASK) & UserGranule::ALIGN;
+        if first_unused < self.brk {
+            self.unmap_user(RangeInclusive::new(first_unused, self.brk - 1));
+        }
+        self.brk = brk;
+        Ok(brk)
+    }
+
+    /// Reserve `len` bytes in the mmap memory, backed on the first access.
+    pub fn mmap(&mut self, len: usize) -> Result<usize, &'static str> {
+        if len == 0 {
+            return Err("empty mapping");
+        }
+        let len = (len + UserGranule::MASK) & UserGranule::ALIGN;
+        let mut mappings: Vec<&RangeInclusive<usize>> = self.lazy_regions.iter()
+            .map(|r| &r.range)
+            .filter(|r| *r.start() >= MMAP_START)
+            .collect();
+        mappings.sort_by_key(|r| *r.start());
+
+        // first hole large enough
+        let mut start = MMAP_START;
+        for mapping in mappings {
+            if start + len <= *mapping.start() {
+                break;
+            }
+            start = mapping.end() + 1;
+        }
+        if start + len - 1 > MMAP_END {
+            return Err("no more mmap memory");
+        }
+        self.lazy_regions.push(LazyRegion {
+            range: RangeInclusive::new(start, start + len - 1),
+            attribute_fields: USER_DATA,
+        });
+        Ok(start)
+    }
+
+    /// Remove a whole mapping returned by `mmap`.
+    pub fn munmap(&mut self, addr: usize, len: usize) -> Result<(), &'static str> {
+        let end = addr + ((len + UserGranule::MASK) & UserGranule::ALIGN) - 1;
+        let index = self.lazy_regions.iter()
+            .position(|r| addr >= MMAP_START && *r.range.start() == addr && *r.range.end() == end)
+            .ok_or("not a mapping")?;
+        let region = self.lazy_regions.remove(index);
+        self.unmap_user(region.range);
+        Ok(())
+    }
+
+    /// Make the process resume in a signal handler : its context and its FP/SIMD state (which must
+    /// be saved from the core first) are saved in a frame pushed on its stack, and the handler returns to the restorer which gives the frame to sigreturn.
+    pub fn enter_handler(&mut self, sig: u32, handler: usize, restorer: usize) -> Result<(), &'static str> {
+        let frame = (self.context.stack as usize).checked_sub(size_of::<SignalFrame>())
+            .ok_or("no room on the stack for the signal frame")? & !0xF;
+        let saved = SignalFrame { context: self.context, fp: self.fp };
+        let bytes = unsafe {
+            slice::from_raw_parts(&saved as *const SignalFrame as *const u8, size_of::<SignalFrame>())
+        };
+        self.copy_to_user(frame, bytes)?;
+        self.context.regs.x[0] = sig as u64;
+        self.context.regs.x[30] = restorer as u64;
+        self.context.eret_addr = handler as u64;
+        self.context.stack = frame as u64;
+        Ok(())
+    }
+
+    /// Resume the context and the FP/SIMD state saved in the signal frame at `frame`, when a
+    /// handler returns. The FP/SIMD registers of the core must be reloaded from `fp`.
+    pub fn leave_handler(&mut self, frame: usize) -> Result<(), &'static str> {
+        let mut saved = SignalFrame { context: ProcessContext::default(), fp: FpState::new() };
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(&mut saved as *mut SignalFrame as *mut u8, size_of::<SignalFrame>())
+        };
+        // the saved state is in user memory, but `restore` always returns to EL0
+        self.copy_from_user(frame, bytes)?;
+        self.context = saved.context;
+        self.fp = saved.fp;
+        Ok(())
+    }
+
+    /// Statistics of the process reported to the programs.
+    pub fn info(&self) -> ProcInfo {
+        ProcInfo {
+            pid: self.pid,
+            parent: self.parent,
+            nice: self.nice as i32,
+            state: match self.state {
+                Ready => ProcState::Ready,
+                Running => ProcState::Running,
+                ProcessState::Blocked(_) => ProcState::Blocked,
+                ProcessState::Sleeping(_) => ProcState::Sleeping,
+                Zombie(_) => ProcState::Zombie,
+            },
+            _reserved: 0,
+            cpu_time: self.accounting.cpu_time,
+            switches: self.accounting.switches,
+            memory: (self.pages.len() * UserGranule::SIZE) as u64,
+        }
+    }
+
+    /// Kernel object behind the handle `index`.
+    pub fn handle(&self, index: usize) -> Option<Handle> {
+        self.handles.get(index).copied().flatten()
+    }
+
+    /// Give a handle to `handle`, in the first free slot, returns its index.
+    pub fn add_handle(&mut self, handle: Handle) -> usize {
+        match self.handles.iter().position(|h| h.is_none()) {
+            Some(index) => {
+                self.handles[index] = Some(handle);
+                index
+            },
+            None => {
+                self.handles.push(Some(handle));
+                self.handles.len() - 1
+            }
+        }
+    }
+
+    pub fn state(&self) -> ProcessState {
+        self.state
+    }
+
+    pub fn nice(&self) -> i8 {
+        self.nice
+    }
+
+    pub fn set_nice(&mut self, nice: i8) {
+        self.nice = nice;
+    }
+
+    pub fn is_runnable(&self) -> bool {
+        self.state == Ready
+    }
+
+    /// Set the context the process will resume with.
+    pub fn set_context(&mut self, context: ProcessContext) {
+        self.context = context;
+    }
+
+    /// Set the value returned by the syscall the process is blocked in.
+    pub fn set_return(&mut self, x0: u64, x1: u64) {
+        self.context.regs.x[0] = x0;
+        self.context.regs.x[1] = x1;
+    }
+
+    /// Stop running the process until it is woken up.
+    pub fn block(&mut self, state: ProcessState, e: &ExceptionContext) {
+        self.stop_running();
+        self.context = ProcessContext::new(e.gpr, e.spsr_el1, e.elr_el1, e.stack_el0);
+        self.state = state;
+    }
+
+    /// Account the time since the process got the CPU.
+    fn stop_running(&mut self) {
+        if self.state == Running {
+            self.accounting.cpu_time += TIMER.now() - self.accounting.last_run;
+        }
+    }
+
+    pub fn wake(&mut self) {
+        self.state = Ready;
+    }
+
+    pub fn exit(&mut self, code: i32) {
+        self.stop_running();
+        self.state = Zombie(code);
+        self.release_memory();
+        // the core switches to other tables before the scheduler is released, nothing translates
+        // with these ones anymore
+        unsafe { self.tlb.free(&mut *FRAMES.lock()) };
+        self.handles.clear();
     }
 
     pub fn is_running(&self) -> bool {
@@ -98,13 +711,17 @@ impl Process {
 
     pub fn pause(&mut self, gpr: &GPR, state: u64, eret_addr: u64, stack: u64) {
         if self.state == Running {
-            self.state = Sleep;
+            self.stop_running();
+            self.state = Ready;
             self.context = ProcessContext::new(*gpr, state, eret_addr, stack);
         }
     }
 
-    pub fn restore(&mut self, stack: u64) {
+    pub fn restore(&mut self, stack: u64) -> ! {
+        self.stop_running();
         self.state = Running;
+        self.accounting.last_run = TIMER.now();
+        self.accounting.switches += 1;
         switch_user_tables(self.pid, self.tlb.phys_base_addr() as u64);
         SPSR_EL1.set(self.context.state);
         ELR_EL1.set(self.context.eret_addr);
@@ -115,10 +732,29 @@ impl Process {
     }
 }
 
-pub(crate) fn create_tmp_init_program() {
-    unsafe { SCHEDULER.create_process(include_bytes!("../../../program.img")) };
+impl Drop for Process {
+    fn drop(&mut self) {
+        // the memory of a zombie is already given back
+        if !matches!(self.state, Zombie(_)) {
+            self.release_memory();
+            unsafe { self.tlb.free(&mut *FRAMES.lock()) };
+        }
+    }
+}
+
+/// Executable at `path` in the initramfs.
+pub(crate) fn find_program(path: &str) -> Option<&'static [u8]> {
+    INITRAMFS.lock().find(path)
+}
+
+/// Called by `__restore_and_eret` once the registers of the process are on the kernel stack, the
+/// guard of the scheduler is never dropped on the way to the process.
+#[no_mangle]
+extern "C" fn __release_scheduler() {
+    unsafe { SCHEDULER.force_unlock() }
 }
 
 pub(crate) fn create_init_program() {
-    unsafe { SCHEDULER.create_process(include_bytes!("../../../init.img")) };
+    let bytes = find_program("/bin/init").expect("init program is missing from the initramfs");
+    SCHEDULER.lock().create_process(bytes).expect("init program can not be loaded");
 }
\ No newline at end of file
@@ -0,0 +1,93 @@
+use mmio::syscall::{SIGILL, SIGKILL, SIGSEGV, SIGTERM, NB_SIGNALS, SIG_DFL, SIG_IGN};
+
+/// What a process does when it receives a signal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    Default,
+    Ignore,
+    /// run the user handler, which returns to the restorer to call sigreturn
+    Handler { handler: usize, restorer: usize },
+}
+
+/// Signals sent to a process and not delivered yet, with the actions it set.
+#[derive(Debug, Copy, Clone)]
+pub struct Signals {
+    pending: u32,
+    actions: [Action; NB_SIGNALS as usize],
+}
+
+impl Signals {
+    pub const fn new() -> Self {
+        Signals {
+            pending: 0,
+            actions: [Action::Default; NB_SIGNALS as usize],
+        }
+    }
+
+    pub fn is_valid(sig: u32) -> bool {
+        sig > 0 && sig < NB_SIGNALS
+    }
+
+    /// Terminate the process on the default action.
+    pub fn is_fatal(sig: u32) -> bool {
+        matches!(sig, SIGILL | SIGKILL | SIGSEGV | SIGTERM)
+    }
+
+    pub fn raise(&mut self, sig: u32) {
+        self.pending |= 1 << sig;
+    }
+
+    /// Lowest pending signal, removed from the pending ones.
+    pub fn take_pending(&mut self) -> Option<u32> {
+        if self.pending == 0 {
+            return None;
+        }
+        let sig = self.pending.trailing_zeros();
+        self.pending &= !(1 << sig);
+        Some(sig)
+    }
+
+    pub fn action(&self, sig: u32) -> Action {
+        if sig == SIGKILL { Action::Default } else { self.actions[sig as usize] }
+    }
+
+    /// True when receiving `sig` does nothing.
+    pub fn ignores(&self, sig: u32) -> bool {
+        match self.action(sig) {
+            Action::Ignore => true,
+            Action::Default => !Signals::is_fatal(sig),
+            Action::Handler { .. } => false,
+        }
+    }
+
+    /// Set the action of `sig` from the handler given to sigaction, returns the previous handler.
+    pub fn set_action(&mut self, sig: u32, handler: usize, restorer: usize) -> Result<usize, &'static str> {
+        if !Signals::is_valid(sig) || sig == SIGKILL {
+            return Err("signal can't be caught");
+        }
+        let previous = match self.actions[sig as usize] {
+            Action::Default => SIG_DFL,
+            Action::Ignore => SIG_IGN,
+            Action::Handler { handler, .. } => handler,
+        };
+        self.actions[sig as usize] = match handler {
+            SIG_DFL => Action::Default,
+            SIG_IGN => Action::Ignore,
+            _ => Action::Handler { handler, restorer },
+        };
+        Ok(previous)
+    }
+
+    /// Back to the default actions, when the handlers are gone with the program they were in.
+    pub fn reset_actions(&mut self) {
+        self.actions = [Action::Default; NB_SIGNALS as usize];
+    }
+
+    /// Signals inherited by a forked child : the same actions and nothing pending.
+    pub fn inherit(&self) -> Self {
+        Signals {
+            pending: 0,
+            actions: self.actions,
+        }
+    }
+}
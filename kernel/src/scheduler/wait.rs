@@ -0,0 +1,25 @@
+use alloc::collections::VecDeque;
+
+/// Processes blocked on the same event, in the order they blocked. The driver raising the event
+/// wakes them up through the scheduler.
+pub struct WaitQueue {
+    pids: VecDeque<u16>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            pids: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, pid: u16) {
+        if !self.pids.contains(&pid) {
+            self.pids.push_back(pid);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<u16> {
+        self.pids.pop_front()
+    }
+}
@@ -0,0 +1,133 @@
+use core::arch::{asm, global_asm};
+use core::ops::RangeInclusive;
+use aarch64_cpu::asm::sev;
+use aarch64_cpu::registers::{MPIDR_EL1, MAIR_EL1, TCR_EL1, TTBR1_EL1, SCTLR_EL1, Readable};
+use shared::memory::mapping::{Descriptor, Mapping, Translation, AttributeFields, MemAttributes, AccessPermissions};
+use shared::memory::mmu::{enable_protections, memory_flush, switch_user_tables, UserGranule, VA_BITS, VIRTUAL_ADDR_START};
+use shared::memory::walker::PageTable;
+use mmio::IRQ;
+use tock_registers::interfaces::Writeable;
+use crate::memory::map;
+use crate::global::{BCMDEVICES, TIMER, SCHEDULER, FRAMES};
+use crate::{exceptions, scheduler};
+
+global_asm!(include_str!("smp.S"));
+
+pub const NB_CORES: usize = 4;
+/// Stacks of the secondary cores, above the one of the first core
+pub const CORE_STACK_SIZE: usize = 0x0002_0000;
+/// Offsets in the spin table page the firmware polls to release the secondary cores
+const SPIN_TABLE: [usize; NB_CORES] = [0xD8, 0xE0, 0xE8, 0xF0];
+
+extern "C" {
+    fn __secondary_start();
+}
+
+/// What the secondary cores need to turn their MMU on, read by smp.S before the MMU is on.
+#[repr(C)]
+struct SmpBoot {
+    mair: u64,
+    tcr: u64,
+    /// tables identity mapping the kernel, while smp.S runs at its physical address
+    ttbr0: u64,
+    ttbr1: u64,
+    sctlr: u64,
+    stacks: [u64; NB_CORES],
+}
+
+#[no_mangle]
+static mut SMP_BOOT: SmpBoot = SmpBoot {
+    mair: 0,
+    tcr: 0,
+    ttbr0: 0,
+    ttbr1: 0,
+    sctlr: 0,
+    stacks: [0; NB_CORES],
+};
+
+/// Index of the core running the code.
+#[inline]
+pub fn core_id() -> usize {
+    (MPIDR_EL1.get() & 0x3) as usize
+}
+
+/// Release the secondary cores from the spin table, they join the scheduler once started. The
+/// running core must already use the user granule, as its TCR is copied.
+pub fn start_cores() -> Result<(), &'static str> {
+    let identity: [Descriptor; 1] = [Descriptor {
+        virtual_range: || RangeInclusive::new(map::physical::KERN_START, map::physical::KERN_END),
+        map: Mapping {
+            translation: Translation::Identity,
+            attribute_fields: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                // only executed until the MMU is on, WXN makes the writable memory execute never
+                acc_perms: AccessPermissions::ReadOnlyKernel,
+                execute_never: false,
+            },
+        },
+    }];
+    // it is used by every core starting, so it is never freed
+    let mut frames = FRAMES.lock();
+    let mut tables = PageTable::<UserGranule>::new(&mut *frames, VA_BITS)?;
+    tables.map_descriptors(&mut *frames, &identity)?;
+    drop(frames);
+    memory_flush();
+
+    unsafe {
+        SMP_BOOT = SmpBoot {
+            mair: MAIR_EL1.get(),
+            tcr: TCR_EL1.get(),
+            ttbr0: tables.phys_base_addr() as u64,
+            ttbr1: TTBR1_EL1.get(),
+            sctlr: SCTLR_EL1.get(),
+            stacks: core::array::from_fn(|core| (map::virt::KERN_STACK_START + core * CORE_STACK_SIZE) as u64),
+        };
+        // smp.S reads it with the MMU, so the caches, off
+        clean_dcache(core::ptr::addr_of!(SMP_BOOT) as usize, core::mem::size_of::<SmpBoot>());
+
+        let entry = __secondary_start as *const () as usize & !VIRTUAL_ADDR_START;
+        for core in 1..NB_CORES {
+            let release = crate::memory::phys_to_virt(map::physical::SPIN_TABLE_START + SPIN_TABLE[core]) as *mut u64;
+            release.write_volatile(entry as u64);
+            clean_dcache(release as usize, 8);
+        }
+    }
+    sev();
+    Ok(())
+}
+
+/// Kernel entry of the secondary cores, from smp.S.
+#[no_mangle]
+unsafe extern "C" fn __secondary_main(core: usize) -> ! {
+    enable_protections();
+    exceptions::init();
+    scheduler::fp::trap();
+    let idle_tables = SCHEDULER.lock().idle_tables();
+    switch_user_tables(0, idle_tables);
+    BCMDEVICES.lock().CORE_MAILBOX_IRQCNTL[core].set(1);
+    TIMER.setup(&BCMDEVICES.lock(), core);
+    SCHEDULER.lock().start_core(core);
+    debugln!("core {} started", core);
+
+    IRQ::enable();
+    scheduler::idle::idle()
+}
+
+/// Raise the mailbox IRQ of `core`, when it has a process to run while it is idle.
+pub fn wake_core(core: usize) {
+    BCMDEVICES.lock().CORE_MAILBOX_SET[core][0].set(1);
+}
+
+/// Acknowledge the mailbox IRQ of the running core.
+pub fn clear_wake() {
+    BCMDEVICES.lock().CORE_MAILBOX_CLEAR[core_id()][0].set(u32::MAX);
+}
+
+/// Write the cache lines of `[addr, addr + len)` back to the memory.
+fn clean_dcache(addr: usize, len: usize) {
+    const LINE: usize = 64;
+    for line in (addr & !(LINE - 1)..addr + len).step_by(LINE) {
+        unsafe { asm!("dc civac, {}", in(reg) line) };
+    }
+    unsafe { asm!("dsb sy") };
+}
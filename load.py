@@ -3,6 +3,7 @@ import serial
 import os
 
 kernel_img = "kernel-high.img"
+initramfs_img = "initramfs.cpio"
 
 def ack(ser):
    i = 0
@@ -12,10 +13,19 @@ def ack(ser):
       if c == b'\x03':
          i += 1
 
-def send_kernel(ser, data):
+def send_image(ser, data):
+   ack(ser)
+   print(f"write {len(data):d}")
+   ser.write(len(data).to_bytes(4, byteorder='big'))
+   ack(ser)
+
+   print(f"size received {read_dword(ser):d}")
+   ack(ser)
+
    while data:
       sent = ser.write(data)
       data = data[sent:]
+   ack(ser)
 
 
 def create_serial() :
@@ -28,27 +38,21 @@ def create_serial() :
 def read_dword(ser):
    return int.from_bytes(ser.read(4), byteorder='big', signed=False)
 
-def read_kernel():
-   f = open(kernel_img, "rb")
+def read_image(name):
+   if not os.path.exists(name):
+      return b""
+   f = open(name, "rb")
    data = f.read()
    f.close()
    return data
 
 s = create_serial()
 while 1:
-   ack(s)
-
-   kernel_size = os.path.getsize(kernel_img)
-   print(f"write {kernel_size:d}")
-   s.write(kernel_size.to_bytes(4, byteorder='big'))
-   ack(s)
-
-   print(f"kernel size received {read_dword(s):d}")
-   ack(s)
-
    print("send kernel")
-   send_kernel(s, read_kernel())
-   ack(s)
+   send_image(s, read_image(kernel_img))
+
+   print("send initramfs")
+   send_image(s, read_image(initramfs_img))
 
    print("kernel sent")
    line = ""
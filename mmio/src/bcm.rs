@@ -1,5 +1,5 @@
 use tock_registers::{
-    registers::{ ReadWrite, ReadOnly},
+    registers::{ ReadWrite, ReadOnly, WriteOnly},
     register_bitfields,
 };
 use core::ops;
@@ -65,11 +65,19 @@ pub struct DeviceMemoryBlock {
     pub LOCAL_TIMER_IRQ_CLEAN_RELOAD: ReadWrite<u32, LOCAL_TIMER_IRQ_CLEAN_RELOAD::Register>,
     // 0x38
     __reserved_3: u32,
-    pub CORE0_TIMER_IRQCNTL: ReadWrite<u32>,
-    // 0x40
-    __reserved_4: [u32; 7],
-    pub CORE0_INTERRUPT_SOURCE: ReadOnly<u32>,
+    // 0x40, one register per core
+    pub CORE_TIMER_IRQCNTL: [ReadWrite<u32>; 4],
+    // 0x50
+    pub CORE_MAILBOX_IRQCNTL: [ReadWrite<u32>; 4],
     // 0x60
+    pub CORE_INTERRUPT_SOURCE: [ReadOnly<u32>; 4],
+    // 0x70
+    __reserved_4: [u32; 4],
+    // 0x80, 4 mailboxes per core, writing sets bits and raises the mailbox IRQ of the core
+    pub CORE_MAILBOX_SET: [[WriteOnly<u32>; 4]; 4],
+    // 0xC0, writing clears bits
+    pub CORE_MAILBOX_CLEAR: [[ReadWrite<u32>; 4]; 4],
+    // 0x100
 }
 
 
@@ -29,10 +29,10 @@ pub use irq::IRQ;
 pub use bcm::BCMDeviceMemory;
 pub use console::FrameBufferConsole;
 use linked_list_allocator::LockedHeap;
+use shared::sync::IrqSafeMutex;
 
-pub static mut LOGGER: Logger = Logger::new();
-pub static mut SCREEN: Logger = Logger::new();
+/// Debug output of the boot loader and the kernel.
+pub static LOGGER: IrqSafeMutex<Logger> = IrqSafeMutex::new(Logger::new());
+/// Console output of the kernel and of the programs, which lock it without masking at EL0.
+pub static SCREEN: IrqSafeMutex<Logger> = IrqSafeMutex::new(Logger::new());
 pub static DMA: LockedHeap = LockedHeap::empty();
-
-#[global_allocator]
-pub static HEAP: LockedHeap = LockedHeap::empty();
@@ -29,13 +29,9 @@ macro_rules! println {
 
 #[doc(hidden)]
 pub fn _debug(args: Arguments) {
-    unsafe {
-        LOGGER.write_fmt(args).unwrap();
-    }
+    LOGGER.lock().write_fmt(args).unwrap();
 }
 
 pub fn _print(args: Arguments) {
-    unsafe {
-        SCREEN.write_fmt(args).unwrap();
-    }
+    SCREEN.lock().write_fmt(args).unwrap();
 }
\ No newline at end of file
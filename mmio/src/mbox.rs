@@ -60,6 +60,8 @@ pub struct RegisterBlock {
 pub enum MboxError {
     ResponseError,
     UnknownError,
+    /// the buffer is not mapped, or out of the memory the VideoCore reaches
+    AddressError,
 }
 
 // Channels
@@ -71,6 +73,8 @@ pub mod channel {
 #[allow(dead_code)]
 pub mod tag {
     pub const GETSERIAL: u32 = 0x10004;
+    pub const GET_ARM_MEMORY: u32 = 0x10005;
+    pub const GET_VC_MEMORY: u32 = 0x10006;
     pub const SETCLKRATE: u32 = 0x38002;
 
     pub const GET_SCREEN_FRAME_BUFFER: u32 = 0x40001;
@@ -161,6 +165,23 @@ impl<'a> Mbox<'a> {
         self.call(if self.is_dma { self.dma } else { &self.stack }, channel)
     }
 
+    /// Base address and size of the memory reserved for the ARM cores.
+    pub fn arm_memory(&mut self) -> Result<(usize, usize), MboxError> {
+        self.memory(mbox::tag::GET_ARM_MEMORY)
+    }
+
+    /// Base address and size of the memory reserved for the VideoCore.
+    pub fn vc_memory(&mut self) -> Result<(usize, usize), MboxError> {
+        self.memory(mbox::tag::GET_VC_MEMORY)
+    }
+
+    fn memory(&mut self, tag: u32) -> Result<(usize, usize), MboxError> {
+        self.clear();
+        self.prepare(tag, 8, 0, &[]);
+        self.request(mbox::channel::PROP)?;
+        Ok((self.get_at_pos(5) as usize, self.get_at_pos(6) as usize))
+    }
+
     /// TODO: should become private once request is implemented everywhere
     pub fn call(&self, buffer: &[u32], channel: u32) -> Result<(), MboxError> {
         // wait until we can write to the mailbox
@@ -170,7 +191,9 @@ impl<'a> Mbox<'a> {
             }
             unsafe { asm!("nop") };
         }
-        let buf_ptr = buffer.as_ptr() as u32;
+        let buf_ptr = Self::bus_address(buffer)?;
+        // the VideoCore reads the memory, not the caches of the core
+        Self::clean_invalidate(buffer);
         // write the address of our message to the mailbox with channel identifier
         self.WRITE.set((buf_ptr & !0xF) | (channel & 0xF));
 
@@ -189,8 +212,10 @@ impl<'a> Mbox<'a> {
 
             // is it a response to our message?
             if ((resp & 0xF) == channel) && ((resp & !0xF) == buf_ptr) {
+                // drop the lines the core may have fetched meanwhile, the answer is in the memory
+                Self::clean_invalidate(buffer);
                 // is it a valid successful response?
-                return match buffer[1] {
+                return match unsafe { core::ptr::read_volatile(&buffer[1]) } {
                     response::SUCCESS => Ok(()),
                     response::ERROR => Err(MboxError::ResponseError),
                     _ => Err(MboxError::UnknownError)
@@ -200,6 +225,32 @@ impl<'a> Mbox<'a> {
         }
     }
 
+    /// Physical address of `buffer`, as translated by the tables of the core (the address itself
+    /// while the MMU is off), which is where the VideoCore finds it.
+    fn bus_address(buffer: &[u32]) -> Result<u32, MboxError> {
+        let addr = buffer.as_ptr() as u64;
+        let par: u64;
+        unsafe {
+            asm!("at s1e1r, {addr}", "isb", "mrs {par}, par_el1", addr = in(reg) addr, par = out(reg) par);
+        }
+        // PAR_EL1.F is set when the translation faults
+        if par & 1 != 0 {
+            return Err(MboxError::AddressError);
+        }
+        let phys = (par & 0x0000_FFFF_FFFF_F000) | (addr & 0xFFF);
+        u32::try_from(phys).map_err(|_| MboxError::AddressError)
+    }
+
+    /// Write the cache lines of `buffer` back to the memory, and drop them.
+    fn clean_invalidate(buffer: &[u32]) {
+        const LINE: usize = 64;
+        let start = buffer.as_ptr() as usize;
+        for line in (start & !(LINE - 1)..start + mem::size_of_val(buffer)).step_by(LINE) {
+            unsafe { asm!("dc civac, {}", in(reg) line) };
+        }
+        unsafe { asm!("dsb sy") };
+    }
+
     fn set_and_inc(&mut self, value: u32) -> () {
         let pos = self.inc();
         self.set_at_pos(value, pos)
@@ -213,6 +264,14 @@ impl<'a> Mbox<'a> {
         }
     }
 
+    fn get_at_pos(&self, position: usize) -> u32 {
+        if self.is_dma {
+            self.dma[position]
+        } else {
+            self.stack[position]
+        }
+    }
+
     fn inc(&mut self) -> usize {
         let ret = self.pos;
         self.pos = self.pos + 1;
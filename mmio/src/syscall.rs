@@ -1,5 +1,83 @@
-use crate::io::{Writer, IoResult};
-use core::arch::asm;
+use crate::io::{Writer, IoResult, IOError};
+use core::arch::{asm, naked_asm};
+
+/// Signals a program can send with `kill`, the fatal ones terminate the receiver with exit code
+/// 128 + signal unless it set a handler with `sigaction`. SIGKILL can't be caught.
+pub const SIGILL: u32 = 4;
+pub const SIGKILL: u32 = 9;
+pub const SIGSEGV: u32 = 11;
+pub const SIGTERM: u32 = 15;
+pub const NB_SIGNALS: u32 = 32;
+
+/// Handlers given to `sigaction` for the default action and to ignore the signal.
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// Longest message `send`, `call` and `reply` carry.
+pub const MSG_MAX: usize = 256;
+/// Handle index for the messages carrying no capability.
+pub const NO_HANDLE: i64 = -1;
+/// Returned by the syscalls given a buffer out of the memory of the program.
+pub const EFAULT: i64 = -14;
+/// Longest string printed by one `write` syscall.
+pub const PRINT_MAX: usize = 4096;
+
+/// State of a process, as reported by `proc_info`.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProcState {
+    Ready = 0,
+    Running = 1,
+    Blocked = 2,
+    Sleeping = 3,
+    Zombie = 4,
+}
+
+impl ProcState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcState::Ready => "ready",
+            ProcState::Running => "running",
+            ProcState::Blocked => "blocked",
+            ProcState::Sleeping => "sleeping",
+            ProcState::Zombie => "zombie",
+        }
+    }
+}
+
+/// Statistics of a process, filled by `proc_info`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ProcInfo {
+    pub pid: u16,
+    /// 0 when the parent is the kernel
+    pub parent: u16,
+    pub nice: i32,
+    pub state: ProcState,
+    /// always 0, so the structure has no padding to copy to the programs
+    pub _reserved: u32,
+    /// ticks of the physical counter spent running
+    pub cpu_time: u64,
+    /// times the process got the CPU
+    pub switches: u64,
+    /// bytes of user memory backed by frames, shared frames included
+    pub memory: u64,
+}
+
+impl ProcInfo {
+    pub const fn empty() -> Self {
+        ProcInfo {
+            pid: 0,
+            parent: 0,
+            nice: 0,
+            state: ProcState::Ready,
+            _reserved: 0,
+            cpu_time: 0,
+            switches: 0,
+            memory: 0,
+        }
+    }
+}
 
 pub struct SysCall {
 
@@ -7,12 +85,21 @@ pub struct SysCall {
 
 impl Writer for SysCall {
 
-    /// Display a string
+    /// Display a string, the kernel prints up to PRINT_MAX bytes at a time
     fn write(&mut self, bytes: &[u8]) -> IoResult<usize> {
-        unsafe {
-            asm!("SVC 1", in("x0") bytes.as_ptr(), in("x1") bytes.len());
+        let mut written = 0;
+        while written < bytes.len() {
+            let len = (bytes.len() - written).min(PRINT_MAX);
+            let result: i64;
+            unsafe {
+                asm!("SVC 1", inlateout("x0") bytes[written..].as_ptr() => result, in("x1") len, clobber_abi("C"));
+            }
+            if result <= 0 {
+                return Err(IOError::UnknownError);
+            }
+            written += result as usize;
         }
-        Ok(bytes.len())
+        Ok(written)
     }
 
 }
@@ -31,5 +118,202 @@ impl SysCall {
         }
     }
 
+    /// Duplicate the current program, returns 0 in the child and the child PID in the parent
+    pub fn fork(&self) -> i64 {
+        let pid: i64;
+        unsafe {
+            asm!("SVC 4", lateout("x0") pid, clobber_abi("C"));
+        }
+        pid
+    }
+
+    /// Replace the current program by the one at `path` in the initramfs, only returns (-1) on
+    /// failure
+    pub fn exec(&self, path: &str) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 5", inlateout("x0") path.as_ptr() => result, in("x1") path.len(), clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Terminate the current program
+    pub fn exit(&self, code: i32) -> ! {
+        unsafe {
+            asm!("SVC 6", in("x0") code, options(noreturn));
+        }
+    }
+
+    /// Wait for a child to exit (-1 for any child), returns its PID and exit code
+    pub fn waitpid(&self, pid: i64) -> (i64, i32) {
+        let (child, code): (i64, i64);
+        unsafe {
+            asm!("SVC 7", inlateout("x0") pid => child, lateout("x1") code, clobber_abi("C"));
+        }
+        (child, code as i32)
+    }
+
+    /// Set the nice value (-20 highest priority, 19 lowest) of the current program (`pid` 0) or
+    /// of one of its children, returns -1 on failure. Only root can lower a nice value
+    pub fn set_priority(&self, pid: u16, nice: i8) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 8", inlateout("x0") pid as u64 => result, in("x1") nice as i64, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Move the end of the heap to `addr` (0 to read it), returns the new end or -1
+    pub fn brk(&self, addr: usize) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 9", inlateout("x0") addr => result, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Grow the heap by `increment` bytes, returns the previous end or -1
+    pub fn sbrk(&self, increment: usize) -> i64 {
+        let current = self.brk(0);
+        if current < 0 || self.brk(current as usize + increment) < 0 {
+            return -1;
+        }
+        current
+    }
+
+    /// Map `len` bytes of zeroed memory, returns its address or -1
+    pub fn mmap(&self, len: usize) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 10", inlateout("x0") len => result, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Unmap memory returned by `mmap`, returns 0 or -1
+    pub fn munmap(&self, addr: usize, len: usize) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 11", inlateout("x0") addr => result, in("x1") len, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Read the characters received by the UART into `buf`, waiting for at least one, returns how
+    /// many were read
+    pub fn read(&self, buf: &mut [u8]) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 12", inlateout("x0") buf.as_mut_ptr() => result, in("x1") buf.len(), clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Send `sig` to the process `pid`, returns 0 or -1
+    pub fn kill(&self, pid: u16, sig: u32) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 13", inlateout("x0") pid as u64 => result, in("x1") sig, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Run `handler` (an `extern "C" fn(u32)` address, or SIG_DFL/SIG_IGN) when `sig` is received,
+    /// returns the previous handler or -1
+    pub fn sigaction(&self, sig: u32, handler: usize) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 14", inlateout("x0") sig as u64 => result, in("x1") handler,
+                in("x2") sigreturn as *const () as usize, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// PID of the current program
+    pub fn getpid(&self) -> u16 {
+        let pid: u64;
+        unsafe {
+            asm!("SVC 16", lateout("x0") pid, clobber_abi("C"));
+        }
+        pid as u16
+    }
+
+    /// PID of the parent of the current program, 0 for the kernel
+    pub fn getppid(&self) -> u16 {
+        let pid: u64;
+        unsafe {
+            asm!("SVC 17", lateout("x0") pid, clobber_abi("C"));
+        }
+        pid as u16
+    }
+
+    /// Fill `info` with the statistics of the `index`th process, returns 0 or -1 once `index` is
+    /// past the last process
+    pub fn proc_info(&self, index: usize, info: &mut ProcInfo) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 18", inlateout("x0") index => result, in("x1") info as *mut ProcInfo, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Create an IPC endpoint, returns the handle to it, which the children inherit
+    pub fn endpoint(&self) -> i64 {
+        let handle: i64;
+        unsafe {
+            asm!("SVC 19", lateout("x0") handle, clobber_abi("C"));
+        }
+        handle
+    }
+
+    /// Queue `msg` (up to MSG_MAX bytes) in `endpoint`, the receiver gets its own handle to the
+    /// object of the handle `cap` (NO_HANDLE for none). Returns 0, or -1 if the endpoint is full
+    pub fn send(&self, endpoint: usize, msg: &[u8], cap: i64) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 20", inlateout("x0") endpoint => result, in("x1") msg.as_ptr(), in("x2") msg.len(),
+                in("x3") cap, clobber_abi("C"));
+        }
+        result
+    }
+
+    /// Wait for a message on `endpoint`, copied in `buf`. Returns its length (or -1), the PID of
+    /// the sender and the handle to the capability it carries (NO_HANDLE for none)
+    pub fn recv(&self, endpoint: usize, buf: &mut [u8]) -> (i64, u16, i64) {
+        let (len, sender, cap): (i64, u64, i64);
+        unsafe {
+            asm!("SVC 21", inlateout("x0") endpoint => len, inlateout("x1") buf.as_mut_ptr() => sender,
+                inlateout("x2") buf.len() => cap, clobber_abi("C"));
+        }
+        (len, sender as u16, cap)
+    }
+
+    /// Send `msg` as `send` does and wait for the reply, copied in `reply`. Returns its length
+    /// (or -1) and the handle to the capability it carries
+    pub fn call(&self, endpoint: usize, msg: &[u8], reply: &mut [u8], cap: i64) -> (i64, i64) {
+        let (len, reply_cap): (i64, i64);
+        unsafe {
+            asm!("SVC 22", inlateout("x0") endpoint => len, inlateout("x1") msg.as_ptr() => reply_cap,
+                in("x2") msg.len(), in("x3") reply.as_mut_ptr(), in("x4") reply.len(), in("x5") cap,
+                clobber_abi("C"));
+        }
+        (len, reply_cap)
+    }
+
+    /// Answer the last message received from a `call`, returns 0 or -1
+    pub fn reply(&self, msg: &[u8], cap: i64) -> i64 {
+        let result: i64;
+        unsafe {
+            asm!("SVC 23", inlateout("x0") msg.as_ptr() => result, in("x1") msg.len(), in("x2") cap,
+                clobber_abi("C"));
+        }
+        result
+    }
+}
 
+/// Return address of the signal handlers, back to the kernel which resumes the program where the
+/// signal interrupted it. Naked as the signal frame must be on top of the stack.
+#[unsafe(naked)]
+extern "C" fn sigreturn() -> ! {
+    naked_asm!("SVC 15")
 }
@@ -1,6 +1,6 @@
 
 use core::time::Duration;
-use aarch64_cpu::registers::{CNTFRQ_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0, Readable, Writeable};
+use aarch64_cpu::registers::{CNTFRQ_EL0, CNTP_CTL_EL0, CNTP_CVAL_EL0, CNTP_TVAL_EL0, CNTPCT_EL0, Readable, Writeable};
 use tock_registers::interfaces::Writeable as OtherWritable;
 
 use crate::bcm::DeviceMemoryBlock;
@@ -20,8 +20,9 @@ impl PhysicalTimer {
         }
     }
 
-    pub fn setup(&self, device: &DeviceMemoryBlock) {
-        device.CORE0_TIMER_IRQCNTL.set(1u32 << 1u32); // activate IRQ for local timer in the IRQ table
+    /// Start the tick of `core`, each core has its own physical timer.
+    pub fn setup(&self, device: &DeviceMemoryBlock, core: usize) {
+        device.CORE_TIMER_IRQCNTL[core].set(1u32 << 1u32); // activate IRQ for local timer in the IRQ table
         CNTP_TVAL_EL0.set(PhysicalTimer::duration(self.inc));
         CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
     }
@@ -31,9 +32,33 @@ impl PhysicalTimer {
         CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
     }
 
+    /// Fire the next IRQ once the physical counter reaches `deadline`, instead of after the tick.
+    pub fn set_deadline(&self, deadline: u64) {
+        CNTP_CVAL_EL0.set(deadline);
+        CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+    }
+
+    /// Do not fire any IRQ until the counter is reset.
+    pub fn stop(&self) {
+        CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR + CNTP_CTL_EL0::IMASK::SET);
+    }
+
+    /// Current value of the physical counter.
+    pub fn now(&self) -> u64 {
+        CNTPCT_EL0.get()
+    }
+
+    /// Value of the physical counter once `duration` has elapsed, the counter never gets past
+    /// the deadlines too far away.
+    pub fn deadline(&self, duration: Duration) -> u64 {
+        self.now().saturating_add(PhysicalTimer::duration(duration))
+    }
+
+    /// Counter ticks in `duration`, saturated.
     fn duration(duration: Duration) -> u64 {
         let frq : u64 = CNTFRQ_EL0.get();
-        return frq * duration.as_nanos() as u64 / NS_PER_S;
+        let ticks = frq as u128 * duration.as_nanos() / NS_PER_S as u128;
+        ticks.min(u64::MAX as u128) as u64
     }
 
 }
@@ -240,6 +240,15 @@ impl Uart {
         irq.external_enable(1 << 25);
     }
 
+    /// Next received character, if any, without waiting for one.
+    pub fn try_read_char(&self) -> Option<u8> {
+        if self.FR.matches_all(FR::RXFE::SET) {
+            None
+        } else {
+            Some(self.DR.get() as u8)
+        }
+    }
+
     fn putc(&self, c: u8) {
         // wait until we can send
         loop {
@@ -4,40 +4,47 @@
 #![feature(duration_constants)]
 
 #[macro_use] extern crate mmio;
-use mmio::syscall::SysCall;
-use qemu_exit::QEMUExit;
+extern crate alloc;
+use alloc::vec::Vec;
+use mmio::syscall::{SysCall, SIGTERM};
+use mmio::io::Writer;
+use userland::UserHeap;
 
 use aarch64_cpu::registers::{Readable, SP};
 
-extern "C" {
-    // Boundaries of the .bss section, provided by the linker script
-    static mut __bss_start: u64;
-    static mut __bss_end: u64;
-}
+#[global_allocator]
+static HEAP: UserHeap = UserHeap::empty();
 
 #[panic_handler]
 fn my_panic(info: &core::panic::PanicInfo) -> ! {
     println!("{:?}", info);
-    const QEMU_EXIT_HANDLE: qemu_exit::AArch64 = qemu_exit::AArch64::new();
-    QEMU_EXIT_HANDLE.exit_failure()
+    // only the program stops, with the exit code of the Rust panics
+    SysCall {}.exit(101)
+}
+
+extern "C" fn on_terminate(_sig: u32) {
+    // not println, the interrupted code may hold the SCREEN lock
+    let _ = SysCall {}.writes("program received SIGTERM, exiting\n");
+    SysCall {}.exit(0)
 }
 
 /// Entrypoint of the program
 #[link_section = ".text.start"]
 #[no_mangle]
 pub unsafe extern "C" fn _main() -> () {
-
-    r0::zero_bss(&mut __bss_start, &mut __bss_end);
-    mmio::SCREEN.appender(SysCall { }.into());
+    mmio::SCREEN.lock().appender(SysCall { }.into());
 
     println!("show a message using SVC call");
 
     let sys_call = SysCall {};
+    sys_call.sigaction(SIGTERM, on_terminate as *const () as usize);
     let mut count:u128 = 0;
+    let mut shown: Vec<u128> = Vec::new();
     loop {
         if count % 100000000 == 0 {
+            shown.push(count);
             println!("current stack pointer {:x}", SP.get());
-            println!("show string from time to time {}", count);
+            println!("show string from time to time {}, {} times on the heap", count, shown.len());
             sys_call.sleep(1000);
         }
         count = count + 1;
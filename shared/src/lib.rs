@@ -1,5 +1,5 @@
 #![no_std]
 
-extern crate mmio;
 pub mod exceptions;
-pub mod memory;
\ No newline at end of file
+pub mod memory;
+pub mod sync;
\ No newline at end of file
@@ -25,9 +25,29 @@ pub enum Translation {
 pub struct AttributeFields {
     pub mem_attributes: MemAttributes,
     pub acc_perms: AccessPermissions,
+    /// when false, the memory is executable at the level of `acc_perms` only
     pub execute_never: bool,
 }
 
+impl AttributeFields {
+    /// The programs reach the memory.
+    pub fn is_user(&self) -> bool {
+        matches!(self.acc_perms, AccessPermissions::ReadOnlyUser | AccessPermissions::ReadWriteUser)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        matches!(self.acc_perms, AccessPermissions::ReadWriteKernel | AccessPermissions::ReadWriteUser)
+    }
+
+    /// W^X : memory is never both writable and executable.
+    pub fn check_wx(&self) -> Result<(), &'static str> {
+        if self.is_writable() && !self.execute_never {
+            return Err("memory is both writable and executable");
+        }
+        Ok(())
+    }
+}
+
 impl Default for AttributeFields {
     fn default() -> AttributeFields {
         AttributeFields {
@@ -2,10 +2,11 @@ use core::arch::asm;
 use aarch64_cpu::{asm::barrier, registers::*, asm};
 use crate::memory::mapping::{Descriptor};
 use crate::memory::mair;
-use crate::memory::translate::{Granule512MiB, TranslationGranule};
+use crate::memory::translate::Granule512MiB;
 use crate::memory::pages::FixedSizeTranslationTable;
 use core::slice::Iter;
-use mmio::print;
+
+pub use crate::memory::translate::{TranslationGranule, PageGranule, Granule64KiB, Granule4KiB, PageDescriptor};
 
 /// This constant is the power-of-two exponent that defines the virtual address space size.
 ///
@@ -17,6 +18,12 @@ use mmio::print;
 const ADDR_SPACE_SIZE_EXPONENT: usize = 31;
 const NUM_LVL2_TABLES: usize = (1 << ADDR_SPACE_SIZE_EXPONENT) >> Granule512MiB::SHIFT;
 const T0SZ: u64 = (64 - ADDR_SPACE_SIZE_EXPONENT) as u64;
+pub const VA_BITS: usize = ADDR_SPACE_SIZE_EXPONENT;
+
+/// Granules of the kernel (TTBR1) and user (TTBR0) tables, they can differ. The fixed size tables
+/// are 64KiB ones, TTBR0 only moves to the user granule with `switch_user_granule`.
+pub type KernelGranule = Granule64KiB;
+pub type UserGranule = Granule4KiB;
 
 static mut KERNEL_TABLES: ArchTranslationTable = ArchTranslationTable::new();
 static mut USER_TABLES: ArchTranslationTable = ArchTranslationTable::new();
@@ -35,8 +42,8 @@ pub fn init() -> Result<(), &'static str> {
         TCR_EL1::TBI0::Ignored
             + TCR_EL1::TBI1::Ignored
             + TCR_EL1::IPS.val(ips)
-            + TCR_EL1::TG0::KiB_64 // 64 KiB granule
-            + TCR_EL1::TG1::KiB_64 // 64 KiB granule
+            + TCR_EL1::TG0.val(Granule64KiB::TG0)
+            + TCR_EL1::TG1.val(KernelGranule::TG1)
             + TCR_EL1::SH0::Inner
             + TCR_EL1::SH1::Inner
             + TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
@@ -45,8 +52,8 @@ pub fn init() -> Result<(), &'static str> {
             + TCR_EL1::IRGN1::WriteBack_ReadAlloc_WriteAlloc_Cacheable
             + TCR_EL1::EPD0::EnableTTBR0Walks
             + TCR_EL1::EPD1::EnableTTBR1Walks
-            + TCR_EL1::T0SZ.val(T0SZ)  // Start walks at level 2
-            + TCR_EL1::T1SZ.val(T0SZ), // Start walks at level 2
+            + TCR_EL1::T0SZ.val(T0SZ)  // Walks start at Granule64KiB::start_level
+            + TCR_EL1::T1SZ.val(T0SZ), // Walks start at KernelGranule::start_level
     );
 
     // Switch the MMU on.
@@ -86,11 +93,49 @@ pub fn setup_user_tables(descriptors: &[Descriptor]) -> Result<(), &'static str>
     Ok(())
 }
 
-pub fn setup_dyn_user_tables(descriptors: &Iter<Descriptor>, tables: &mut ArchTranslationTable, pid: u16) {
+/// Map the tables of a process, they are only used once switched to with `switch_user_tables`.
+pub fn setup_dyn_user_tables(descriptors: &Iter<Descriptor>, tables: &mut ArchTranslationTable) {
     tables.map_descriptors(descriptors);
-    TTBR0_EL1.write(TTBR0_EL1::ASID.val(pid as u64) + TTBR0_EL1::BADDR.val(tables.phys_base_addr() as u64 >> 1));
     memory_flush();
-    print!("MMU Program mapping at address {:#}: \n{}", tables.phys_base_addr(), tables);
+}
+
+/// Once the tables are W^X, make the writable memory execute never (WXN) for good, and have the
+/// kernel fault on the user memory (PAN, when the core has it). Every core sets PAN for itself.
+pub fn enable_protections() {
+    SCTLR_EL1.set(SCTLR_EL1.get() | SCTLR_EL1::WXN::Enable.value);
+    // the WXN bit may be cached in the TLB
+    memory_flush();
+    // SCTLR_EL1.SPAN is left to 0, the exceptions taken to EL1 set PAN again
+    set_pan(true);
+}
+
+/// PAN is ARMv8.1, the older cores (like the Cortex-A53 of the Raspberry Pi 3) don't have it.
+pub fn has_pan() -> bool {
+    ID_AA64MMFR1_EL1.read(ID_AA64MMFR1_EL1::PAN) != 0
+}
+
+fn set_pan(enable: bool) {
+    if !has_pan() {
+        return;
+    }
+    // msr PAN, #imm, encoded by hand as the target is ARMv8.0
+    unsafe {
+        if enable {
+            asm!(".inst 0xd500419f", options(nomem, nostack));
+        } else {
+            asm!(".inst 0xd500409f", options(nomem, nostack));
+        }
+    }
+}
+
+/// Leave the fixed size user tables for `base_addr`, tables of the user granule. Every core does
+/// it once, before it runs any process.
+pub fn switch_user_granule(base_addr: u64) {
+    let tg0 = TCR_EL1::TG0.val(UserGranule::TG0);
+    memory_flush();
+    TTBR0_EL1.set_baddr(base_addr);
+    TCR_EL1.set((TCR_EL1.get() & !tg0.mask()) | tg0.value);
+    memory_flush();
 }
 
 pub fn switch_user_tables(pid: u16, base_addr : u64) {
@@ -99,7 +144,7 @@ pub fn switch_user_tables(pid: u16, base_addr : u64) {
     memory_flush();
 }
 
-fn memory_flush() {
+pub fn memory_flush() {
     barrier::dsb(barrier::ISHST);
     unsafe {
         asm!("TLBI VMALLE1IS");
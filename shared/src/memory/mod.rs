@@ -4,5 +4,6 @@ mod translate;
 
 pub mod mmu;
 pub mod mapping;
+pub mod walker;
 
 
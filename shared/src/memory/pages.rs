@@ -1,4 +1,4 @@
-use crate::memory::mapping::{AttributeFields, Descriptor, Translation};
+use crate::memory::mapping::{AttributeFields, Descriptor, Mapping, Translation};
 use crate::memory::translate::{PageDescriptor, TableDescriptor, Granule512MiB, TranslationGranule, Granule64KiB};
 use core::ops::RangeInclusive;
 use crate::memory::mmu::VIRTUAL_ADDR_START;
@@ -7,6 +7,9 @@ use core::fmt;
 use itertools::Itertools;
 use core::slice::Iter;
 
+/// Level 3 entries sharing a TLB entry with the contiguous hint, 2 MiB with the 64 KiB granule.
+const CONTIGUOUS_PAGES: usize = 32;
+
 trait BaseAddr {
     fn phys_base_addr(&self) -> usize;
 }
@@ -60,22 +63,70 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<{ NUM_TABLES }> {
     fn page_descriptor_from(&mut self, addr: usize)
                             -> Result<&mut PageDescriptor, &'static str> {
         let (lvl2_index, lvl3_index) = self.lvl2_lvl3_index_from(addr)?;
+        self.split_block(lvl2_index);
         Ok(&mut self.lvl3[lvl2_index][lvl3_index])
     }
 
+    /// Turn a lvl2 block back into its lvl3 table, holding the same mapping page by page, so a
+    /// part of it can be mapped differently.
+    fn split_block(&mut self, lvl2_index: usize) {
+        let lvl2_entry = self.lvl2[lvl2_index];
+        if !lvl2_entry.is_valid() || lvl2_entry.is_table() {
+            return;
+        }
+        let block = PageDescriptor(lvl2_entry.0);
+        for (lvl3_index, page_descriptor) in self.lvl3[lvl2_index].iter_mut().enumerate() {
+            *page_descriptor = block.block_page(lvl3_index << Granule64KiB::SHIFT);
+        }
+        self.lvl2[lvl2_index] = self.lvl3[lvl2_index].phys_base_addr().into();
+    }
+
+    /// Map the range with 512 MiB blocks where the range covers whole lvl2 windows mapped to
+    /// aligned memory, and with 64 KiB pages elsewhere.
     unsafe fn map_pages_at(
         &mut self,
         range: RangeInclusive<usize>,
         translation: &Translation,
         attr: &AttributeFields,
     ) {
-        for phys_page in range.step_by(Granule64KiB::SIZE) {
-            let page_descriptor = self.page_descriptor_from(phys_page).expect("wrong page descriptor");
+        let mut page = *range.start();
+        while page <= *range.end() {
             let output_addr = match translation {
-                Translation::Identity => phys_page,
-                Translation::Offset(a) => a + phys_page,
+                Translation::Identity => page,
+                Translation::Offset(a) => a.wrapping_add(page),
             };
-            *page_descriptor = PageDescriptor::new(output_addr & Granule64KiB::ALIGN, &attr);
+            let window_end = page | Granule512MiB::MASK;
+            if (page | output_addr) & Granule512MiB::MASK == 0 && window_end <= *range.end() {
+                let (lvl2_index, _) = self.lvl2_lvl3_index_from(page).expect("wrong page descriptor");
+                self.lvl2[lvl2_index] = TableDescriptor(PageDescriptor::block(output_addr, attr).0);
+                page = window_end + 1;
+            } else {
+                let page_descriptor = self.page_descriptor_from(page).expect("wrong page descriptor");
+                *page_descriptor = PageDescriptor::new(output_addr & Granule64KiB::ALIGN, attr);
+                page += Granule64KiB::SIZE;
+            }
+        }
+    }
+
+    /// Set the contiguous hint on the aligned groups of pages mapping contiguous memory with the
+    /// same attributes, and clear it on the others.
+    fn update_contiguous_hints(&mut self) {
+        for (lvl2_nr, lvl3) in self.lvl3.iter_mut().enumerate() {
+            if !self.lvl2[lvl2_nr].is_table() {
+                continue;
+            }
+            for group in lvl3.chunks_mut(CONTIGUOUS_PAGES) {
+                let first = group[0].with_contiguous(false);
+                // the output address is in place in the descriptor, the next pages only add to it
+                let contiguous = first.is_valid()
+                    && first.output_addr() & (CONTIGUOUS_PAGES * Granule64KiB::SIZE - 1) == 0
+                    && group.iter().enumerate().all(|(nr, page)| {
+                        page.with_contiguous(false).0 == first.0 + ((nr << Granule64KiB::SHIFT) as u64)
+                    });
+                for page in group.iter_mut().filter(|page| page.is_valid()) {
+                    *page = page.with_contiguous(contiguous);
+                }
+            }
         }
     }
 
@@ -83,12 +134,17 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<{ NUM_TABLES }> {
         return self.lvl2.phys_base_addr();
     }
 
-    pub fn map_descriptors(&mut self, descriptors: &Iter<Descriptor>) {
-
-        // Populate the l2 entries.
+    /// Populate the l2 entries not mapping anything yet, the blocks stay.
+    fn map_lvl2_tables(&mut self) {
         for (lvl2_nr, lvl2_entry) in self.lvl2.iter_mut().enumerate() {
-            *lvl2_entry = self.lvl3[lvl2_nr].phys_base_addr().into();
+            if !lvl2_entry.is_valid() {
+                *lvl2_entry = self.lvl3[lvl2_nr].phys_base_addr().into();
+            }
         }
+    }
+
+    pub fn map_descriptors(&mut self, descriptors: &Iter<Descriptor>) {
+        self.map_lvl2_tables();
 
         for desc in descriptors.as_slice() {
             let range = (desc.virtual_range)();
@@ -96,13 +152,37 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<{ NUM_TABLES }> {
                 self.map_pages_at(range, &desc.map.translation, &desc.map.attribute_fields);
             }
         }
+        self.update_contiguous_hints();
+    }
+
+    /// Remove the mapping of every page in the range.
+    pub fn unmap_range(&mut self, range: RangeInclusive<usize>) {
+        for page in range.step_by(Granule64KiB::SIZE) {
+            *self.page_descriptor_from(page).expect("wrong page descriptor") = PageDescriptor(0);
+        }
+        self.update_contiguous_hints();
+    }
+
+    /// Map a range only known at runtime (e.g. a program segment) on top of the existing layout.
+    pub fn map_range(&mut self, range: RangeInclusive<usize>, map: &Mapping) {
+        self.map_lvl2_tables();
+        unsafe {
+            self.map_pages_at(range, &map.translation, &map.attribute_fields);
+        }
+        self.update_contiguous_hints();
     }
 }
 
 impl<const NUM_TABLES: usize> Display for FixedSizeTranslationTable<{ NUM_TABLES }> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         for (lvl2_nr, lvl2_entry) in self.lvl2.iter().enumerate() {
-            if lvl2_entry.is_valid() {
+            if lvl2_entry.is_valid() && !lvl2_entry.is_table() {
+                let output_addr = PageDescriptor(lvl2_entry.0).output_addr();
+                f.write_fmt(format_args!("Block 0x{:08x} | Virtual 0x{:08x}..0x{:08x} => Physical 0x{:08x}..0x{:08x}\n",
+                                         lvl2_entry as *const _ as usize,
+                                         lvl2_nr << Granule512MiB::SHIFT, (lvl2_nr << Granule512MiB::SHIFT) + Granule512MiB::MASK,
+                                         output_addr, output_addr + Granule512MiB::MASK))?;
+            } else if lvl2_entry.is_valid() {
                 self.lvl3[lvl2_nr].iter()
                     .enumerate()
                     .filter(|l| l.1.is_valid())
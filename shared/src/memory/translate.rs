@@ -4,7 +4,7 @@ use crate::memory::mair;
 use core::{fmt, convert};
 use core::fmt::{Formatter, Display};
 use tock_registers::fields::FieldValue;
-use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::interfaces::{Readable, Writeable, ReadWriteable};
 
 
 // A table descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-15.
@@ -13,6 +13,9 @@ register_bitfields! {u64,
         /// Physical address of the next descriptor.
         NEXT_LEVEL_TABLE_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
 
+        /// Same address, aligned on any granule.
+        NEXT_LEVEL_TABLE_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
+
         TYPE  OFFSET(1) NUMBITS(1) [
             Block = 0,
             Table = 1
@@ -28,15 +31,31 @@ register_bitfields! {u64,
 register_bitfields! {u64,
     // AArch64 Reference Manual page 2150
     pub STAGE1_DESCRIPTOR [
+        /// Unprivileged execute-never
+        UXN      OFFSET(54) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
         /// Privileged execute-never
         PXN      OFFSET(53) NUMBITS(1) [
             False = 0,
             True = 1
         ],
 
+        /// The entry is one of an aligned group of entries mapping contiguous memory with the
+        /// same attributes, which the TLB may cache as a single entry
+        CONTIGUOUS OFFSET(52) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
         /// Physical address of the next table descriptor (lvl2) or the page descriptor (lvl3).
         OUTPUT_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
 
+        /// Same address, aligned on any granule.
+        OUTPUT_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
+
         /// Access flag
         AF       OFFSET(10) NUMBITS(1) [
             False = 0,
@@ -93,6 +112,44 @@ impl TranslationGranule for Granule64KiB {
     const SHIFT: usize = 16;
 }
 
+pub enum Granule4KiB {}
+
+impl TranslationGranule for Granule4KiB {
+    const SIZE: usize = 4 * 1024;
+    const SHIFT: usize = 12;
+}
+
+/// Granules the MMU translates with : the size of the pages and of the tables, each table
+/// resolving `LEVEL_BITS` bits of the address down to the level 3.
+pub trait PageGranule: TranslationGranule {
+    /// Encodings of TCR_EL1.TG0 and TCR_EL1.TG1
+    const TG0: u64;
+    const TG1: u64;
+    const ENTRIES: usize = Self::SIZE / 8;
+    const LEVEL_BITS: usize = Self::SHIFT - 3;
+
+    /// Level of the root table for an address space of `va_bits`, the walks start there.
+    fn start_level(va_bits: usize) -> usize {
+        let levels = (va_bits - Self::SHIFT + Self::LEVEL_BITS - 1) / Self::LEVEL_BITS;
+        4 - levels
+    }
+
+    /// Shift of the address bits resolved at `level`.
+    fn level_shift(level: usize) -> usize {
+        Self::SHIFT + (3 - level) * Self::LEVEL_BITS
+    }
+}
+
+impl PageGranule for Granule64KiB {
+    const TG0: u64 = 0b01;
+    const TG1: u64 = 0b11;
+}
+
+impl PageGranule for Granule4KiB {
+    const TG0: u64 = 0b00;
+    const TG1: u64 = 0b10;
+}
+
 /// A descriptor pointing to the next page table.
 #[derive(Copy, Clone)]
 #[repr(transparent)]
@@ -110,17 +167,28 @@ impl TableDescriptor {
         let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.0);
         return val.read(field);
     }
+
+    /// A block descriptor maps memory instead, before the last level.
+    pub fn is_table(&self) -> bool {
+        InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.0)
+            .matches_all(STAGE1_TABLE_DESCRIPTOR::TYPE::Table)
+    }
+
+    /// Physical address of the next level table.
+    pub fn next_table(&self) -> usize {
+        (self.get(STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_4KiB) as usize) << Granule4KiB::SHIFT
+    }
 }
 
 impl convert::From<usize> for TableDescriptor {
     fn from(next_lvl_table_addr: usize) -> Self {
         let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(0);
 
-        let shifted = next_lvl_table_addr >> Granule64KiB::SHIFT;
+        let shifted = next_lvl_table_addr >> Granule4KiB::SHIFT;
         val.write(
             STAGE1_TABLE_DESCRIPTOR::VALID::True
                 + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
-                + STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_64KiB.val(shifted as u64),
+                + STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_4KiB.val(shifted as u64),
         );
         TableDescriptor(val.get())
     }
@@ -136,7 +204,7 @@ impl Display for TableDescriptor {
     }
 }
 
-/// A page descriptor with 4 KiB aperture.
+/// A page descriptor, with the aperture of the granule of its table.
 ///
 /// The output points to physical memory.
 #[derive(Copy, Clone)]
@@ -148,18 +216,40 @@ impl PageDescriptor {
     pub fn new(output_addr: usize, attribute_fields: &AttributeFields) -> Self {
         let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(0);
 
-        let shifted = output_addr as u64 >> Granule64KiB::SHIFT;
+        let shifted = output_addr as u64 >> Granule4KiB::SHIFT;
         val.write(
             STAGE1_DESCRIPTOR::VALID::True
                 + STAGE1_DESCRIPTOR::AF::True
                 + attribute_fields.clone().into()
                 + STAGE1_DESCRIPTOR::TYPE::Table
-                + STAGE1_DESCRIPTOR::OUTPUT_ADDR_64KiB.val(shifted),
+                + STAGE1_DESCRIPTOR::OUTPUT_ADDR_4KiB.val(shifted),
         );
 
         Self(val.get())
     }
 
+    /// Create a block descriptor, which maps the whole window of an entry before the last level.
+    pub fn block(output_addr: usize, attribute_fields: &AttributeFields) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(Self::new(output_addr, attribute_fields).0);
+        val.modify(STAGE1_DESCRIPTOR::TYPE::Block);
+        Self(val.get())
+    }
+
+    /// Page descriptor of the page at `offset` in this block, with the same attributes.
+    pub fn block_page(&self, offset: usize) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(self.0);
+        let shifted = (self.output_addr() + offset) as u64 >> Granule4KiB::SHIFT;
+        val.modify(STAGE1_DESCRIPTOR::TYPE::Table + STAGE1_DESCRIPTOR::OUTPUT_ADDR_4KiB.val(shifted));
+        Self(val.get())
+    }
+
+    /// Same descriptor, with the contiguous hint set or cleared.
+    pub fn with_contiguous(&self, contiguous: bool) -> Self {
+        let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(self.0);
+        val.modify(if contiguous { STAGE1_DESCRIPTOR::CONTIGUOUS::True } else { STAGE1_DESCRIPTOR::CONTIGUOUS::False });
+        Self(val.get())
+    }
+
     /// Returns the valid bit.
     pub fn is_valid(&self) -> bool {
         InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(self.0)
@@ -170,6 +260,12 @@ impl PageDescriptor {
         let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(self.0);
         return val.read(STAGE1_DESCRIPTOR::OUTPUT_ADDR_64KiB);
     }
+
+    /// Physical address of the page.
+    pub fn output_addr(&self) -> usize {
+        let val = InMemoryRegister::<u64, STAGE1_DESCRIPTOR::Register>::new(self.0);
+        (val.read(STAGE1_DESCRIPTOR::OUTPUT_ADDR_4KiB) as usize) << Granule4KiB::SHIFT
+    }
 }
 
 /// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
@@ -200,12 +296,11 @@ impl convert::From<AttributeFields> for FieldValue<u64, STAGE1_DESCRIPTOR::Regis
             AccessPermissions::ReadWriteUser => STAGE1_DESCRIPTOR::AP::RW_EL1_EL0,
         };
 
-        // Execute Never.
-        desc += if attribute_fields.execute_never {
-            STAGE1_DESCRIPTOR::PXN::True
-        } else {
-            STAGE1_DESCRIPTOR::PXN::False
-        };
+        // Execute Never, code only runs at the level it is mapped for : the kernel never executes
+        // user pages, nor the programs kernel pages.
+        let user = attribute_fields.is_user();
+        desc += STAGE1_DESCRIPTOR::PXN.val((attribute_fields.execute_never || user) as u64)
+            + STAGE1_DESCRIPTOR::UXN.val((attribute_fields.execute_never || !user) as u64);
 
         desc
     }
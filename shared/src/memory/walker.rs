@@ -0,0 +1,269 @@
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+use core::ptr;
+use core::slice;
+use crate::memory::mapping::{AttributeFields, Descriptor, Translation};
+use crate::memory::translate::{PageDescriptor, PageGranule, TableDescriptor};
+
+/// Memory the tables of a `PageTable` are taken from, one frame of at least a granule per table.
+pub trait TableAllocator {
+    /// Physical address of a free frame, the walker clears it.
+    fn alloc_table(&mut self) -> Option<usize>;
+
+    fn free_table(&mut self, table: usize);
+
+    /// Address the code reaches the frame at `table` with.
+    fn table_addr(&self, table: usize) -> usize;
+}
+
+/// Translation tables of any granule, walked from the root level to the level 3 pages. Only the
+/// tables leading to mapped pages are allocated.
+pub struct PageTable<G: PageGranule> {
+    /// physical address of the root table
+    root: usize,
+    start_level: usize,
+    /// size of the address space, as a power of two
+    va_bits: usize,
+    _granule: PhantomData<G>,
+}
+
+impl<G: PageGranule> PageTable<G> {
+    /// Create an empty table for an address space of `va_bits`.
+    pub fn new(allocator: &mut impl TableAllocator, va_bits: usize) -> Result<Self, &'static str> {
+        let root = Self::new_table(allocator)?;
+        Ok(PageTable {
+            root,
+            start_level: G::start_level(va_bits),
+            va_bits,
+            _granule: PhantomData,
+        })
+    }
+
+    pub fn phys_base_addr(&self) -> usize {
+        self.root
+    }
+
+    /// Number of levels a walk goes through.
+    pub fn levels(&self) -> usize {
+        4 - self.start_level
+    }
+
+    /// Map the page at `vaddr` to the physical page at `paddr`, replacing the previous mapping.
+    pub fn map(&mut self, allocator: &mut impl TableAllocator, vaddr: usize, paddr: usize,
+               attr: &AttributeFields) -> Result<(), &'static str> {
+        if (vaddr | paddr) & G::MASK != 0 {
+            return Err("address is not aligned on the granule");
+        }
+        attr.check_wx()?;
+        let entry = self.walk_or_create(allocator, vaddr, 3)?;
+        unsafe { *entry = PageDescriptor::new(paddr, attr).0 };
+        Ok(())
+    }
+
+    /// Map every page of `range`, as given by `translation`. The parts aligned on a level 2 block
+    /// are mapped by blocks, which can't be unmapped or protected page by page.
+    pub fn map_range(&mut self, allocator: &mut impl TableAllocator, range: RangeInclusive<usize>,
+                     translation: &Translation, attr: &AttributeFields) -> Result<(), &'static str> {
+        let block_size = 1 << G::level_shift(2);
+        let mut vaddr = range.start() & G::ALIGN;
+        while vaddr <= *range.end() {
+            let paddr = match translation {
+                Translation::Identity => vaddr,
+                Translation::Offset(a) => a.wrapping_add(vaddr),
+            } & G::ALIGN;
+            let size = if self.start_level <= 2 && (vaddr | paddr) & (block_size - 1) == 0
+                && range.end() - vaddr >= block_size - 1 {
+                self.map_block(allocator, vaddr, paddr, attr)?;
+                block_size
+            } else {
+                self.map(allocator, vaddr, paddr, attr)?;
+                G::SIZE
+            };
+            match vaddr.checked_add(size) {
+                Some(next) => vaddr = next,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Map the level 2 block at `vaddr` to the physical block at `paddr`, both aligned on it.
+    fn map_block(&mut self, allocator: &mut impl TableAllocator, vaddr: usize, paddr: usize,
+                 attr: &AttributeFields) -> Result<(), &'static str> {
+        attr.check_wx()?;
+        let entry = self.walk_or_create(allocator, vaddr, 2)?;
+        if TableDescriptor(unsafe { *entry }).is_valid() {
+            return Err("address is already mapped");
+        }
+        unsafe { *entry = PageDescriptor::block(paddr, attr).0 };
+        Ok(())
+    }
+
+    pub fn map_descriptors(&mut self, allocator: &mut impl TableAllocator,
+                           descriptors: &[Descriptor]) -> Result<(), &'static str> {
+        for desc in descriptors {
+            self.map_range(allocator, (desc.virtual_range)(), &desc.map.translation, &desc.map.attribute_fields)?;
+        }
+        Ok(())
+    }
+
+    /// Unmap the page at `vaddr`, the tables left empty are given back.
+    pub fn unmap(&mut self, allocator: &mut impl TableAllocator, vaddr: usize) -> Result<(), &'static str> {
+        let first_level = self.first_level(vaddr)?;
+        let path = self.path(allocator, vaddr)?;
+        let entry = path[3].1;
+        if !PageDescriptor(unsafe { *entry }).is_valid() {
+            return Err("address is not mapped");
+        }
+        unsafe { *entry = 0 };
+        // the root table stays, even empty
+        for level in (first_level + 1..=3).rev() {
+            let table = path[level].0;
+            if !Self::is_empty(allocator, table) {
+                break;
+            }
+            allocator.free_table(table);
+            unsafe { *path[level - 1].1 = 0 };
+        }
+        Ok(())
+    }
+
+    /// Unmap the pages of `range` which are mapped.
+    pub fn unmap_range(&mut self, allocator: &mut impl TableAllocator, range: RangeInclusive<usize>) {
+        for vaddr in (range.start() & G::ALIGN..=*range.end()).step_by(G::SIZE) {
+            let _ = self.unmap(allocator, vaddr);
+        }
+    }
+
+    /// Give the page mapped at `vaddr` new attributes, it keeps its physical page.
+    pub fn protect(&mut self, allocator: &impl TableAllocator, vaddr: usize,
+                   attr: &AttributeFields) -> Result<(), &'static str> {
+        attr.check_wx()?;
+        let entry = self.walk(allocator, vaddr)?;
+        let descriptor = PageDescriptor(unsafe { *entry });
+        if !descriptor.is_valid() {
+            return Err("address is not mapped");
+        }
+        unsafe { *entry = PageDescriptor::new(descriptor.output_addr(), attr).0 };
+        Ok(())
+    }
+
+    /// Physical address `vaddr` is translated to, if it is mapped.
+    pub fn translate(&self, allocator: &impl TableAllocator, vaddr: usize) -> Option<usize> {
+        self.lookup(allocator, vaddr)
+            .map(|descriptor| descriptor.output_addr() | (vaddr & G::MASK))
+    }
+
+    /// Page descriptor mapping `vaddr`, if it is mapped.
+    pub fn lookup(&self, allocator: &impl TableAllocator, vaddr: usize) -> Option<PageDescriptor> {
+        let entry = self.walk(allocator, vaddr).ok()?;
+        let descriptor = PageDescriptor(unsafe { *entry });
+        descriptor.is_valid().then_some(descriptor)
+    }
+
+    /// Give back every table below the root, which is left empty.
+    pub fn clear(&mut self, allocator: &mut impl TableAllocator) {
+        Self::free_tables(allocator, self.root, self.start_level);
+        Self::clear_table(allocator, self.root);
+    }
+
+    /// Give back every table, root included.
+    ///
+    /// # Safety
+    /// The table must not be used afterwards, nor be the one a core translates with.
+    pub unsafe fn free(&mut self, allocator: &mut impl TableAllocator) {
+        Self::free_tables(allocator, self.root, self.start_level);
+        allocator.free_table(self.root);
+    }
+
+    /// Entry of the level 3 table for `vaddr`, it fails if a table on the way is missing.
+    fn walk(&self, allocator: &impl TableAllocator, vaddr: usize) -> Result<*mut u64, &'static str> {
+        Ok(self.path(allocator, vaddr)?[3].1)
+    }
+
+    /// Table and entry for `vaddr` at every level of the walk, it fails if a table on the way is
+    /// missing. The levels above the first one are left null.
+    fn path(&self, allocator: &impl TableAllocator, vaddr: usize) -> Result<[(usize, *mut u64); 4], &'static str> {
+        let mut path = [(0, ptr::null_mut()); 4];
+        let mut table = self.root;
+        for level in self.first_level(vaddr)?..3 {
+            let entry = Self::entry(allocator, table, vaddr, level);
+            path[level] = (table, entry);
+            let descriptor = TableDescriptor(unsafe { *entry });
+            if !descriptor.is_valid() {
+                return Err("address is not mapped");
+            }
+            if !descriptor.is_table() {
+                return Err("address is mapped by a block");
+            }
+            table = descriptor.next_table();
+        }
+        path[3] = (table, Self::entry(allocator, table, vaddr, 3));
+        Ok(path)
+    }
+
+    /// Entry of the table of `last_level` for `vaddr`, the missing tables on the way are allocated.
+    fn walk_or_create(&mut self, allocator: &mut impl TableAllocator, vaddr: usize,
+                      last_level: usize) -> Result<*mut u64, &'static str> {
+        let mut table = self.root;
+        for level in self.first_level(vaddr)?..last_level {
+            let entry = Self::entry(allocator, table, vaddr, level);
+            let mut descriptor = TableDescriptor(unsafe { *entry });
+            if !descriptor.is_valid() {
+                descriptor = Self::new_table(allocator)?.into();
+                unsafe { *entry = descriptor.0 };
+            } else if !descriptor.is_table() {
+                return Err("address is mapped by a block");
+            }
+            table = descriptor.next_table();
+        }
+        Ok(Self::entry(allocator, table, vaddr, last_level))
+    }
+
+    /// First level of the walk, if `vaddr` is in the address space.
+    fn first_level(&self, vaddr: usize) -> Result<usize, &'static str> {
+        if vaddr >> self.va_bits != 0 {
+            return Err("address is out of the translation table");
+        }
+        Ok(self.start_level)
+    }
+
+    /// Entry for `vaddr` in the `table` of `level`.
+    fn entry(allocator: &impl TableAllocator, table: usize, vaddr: usize, level: usize) -> *mut u64 {
+        let index = (vaddr >> G::level_shift(level)) & (G::ENTRIES - 1);
+        unsafe { (allocator.table_addr(table) as *mut u64).add(index) }
+    }
+
+    fn new_table(allocator: &mut impl TableAllocator) -> Result<usize, &'static str> {
+        let table = allocator.alloc_table().ok_or("no memory left for the translation tables")?;
+        Self::clear_table(allocator, table);
+        Ok(table)
+    }
+
+    fn entries(allocator: &impl TableAllocator, table: usize) -> &[u64] {
+        unsafe { slice::from_raw_parts(allocator.table_addr(table) as *const u64, G::ENTRIES) }
+    }
+
+    fn clear_table(allocator: &impl TableAllocator, table: usize) {
+        let entries = unsafe { slice::from_raw_parts_mut(allocator.table_addr(table) as *mut u64, G::ENTRIES) };
+        entries.iter_mut().for_each(|entry| unsafe { ptr::write_volatile(entry, 0) });
+    }
+
+    fn is_empty(allocator: &impl TableAllocator, table: usize) -> bool {
+        Self::entries(allocator, table).iter().all(|entry| !TableDescriptor(*entry).is_valid())
+    }
+
+    /// Give back the tables below `table` of `level`, the entries pointing to them are left as is.
+    fn free_tables(allocator: &mut impl TableAllocator, table: usize, level: usize) {
+        if level == 3 {
+            return;
+        }
+        for index in 0..G::ENTRIES {
+            let descriptor = TableDescriptor(Self::entries(allocator, table)[index]);
+            if descriptor.is_valid() && descriptor.is_table() {
+                Self::free_tables(allocator, descriptor.next_table(), level + 1);
+                allocator.free_table(descriptor.next_table());
+            }
+        }
+    }
+}
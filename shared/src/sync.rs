@@ -0,0 +1,140 @@
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use aarch64_cpu::registers::{DAIF, Readable, Writeable};
+
+/// Spinlock handing the lock over in the order it was asked for, so no core starves.
+pub struct TicketSpinlock<T> {
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for TicketSpinlock<T> {}
+unsafe impl<T: Send> Sync for TicketSpinlock<T> {}
+
+pub struct SpinlockGuard<'a, T> {
+    lock: &'a TicketSpinlock<T>,
+}
+
+impl<T> TicketSpinlock<T> {
+    pub const fn new(data: T) -> Self {
+        TicketSpinlock {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        SpinlockGuard { lock: self }
+    }
+
+    /// Release the lock taken by a guard which is never dropped, as on the paths leaving the
+    /// kernel with an `eret`.
+    ///
+    /// # Safety
+    /// The caller must own the lock.
+    pub unsafe fn force_unlock(&self) {
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T> Deref for SpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock() }
+    }
+}
+
+/// Set by the boot loader and the kernel once their .bss is cleared. The programs share the
+/// locks of mmio, but they run at EL0 where DAIF traps, and no IRQ runs their code anyway.
+static MASK_EXCEPTIONS: AtomicBool = AtomicBool::new(false);
+
+/// Make the `IrqSafeMutex` mask the exceptions, only from EL1 or above.
+pub fn enable_exception_masking() {
+    MASK_EXCEPTIONS.store(true, Ordering::Relaxed);
+}
+
+/// Ticket spinlock masking the exceptions (DAIF) while it is held, so an interruption on the
+/// same core can not wait for it forever. At EL0 it is a plain spinlock, see
+/// `enable_exception_masking`.
+pub struct IrqSafeMutex<T> {
+    lock: TicketSpinlock<T>,
+}
+
+pub struct IrqSafeGuard<'a, T> {
+    lock: &'a TicketSpinlock<T>,
+    /// DAIF when the lock was taken, restored once released, None when not masked
+    daif: Option<u64>,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(data: T) -> Self {
+        IrqSafeMutex {
+            lock: TicketSpinlock::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> IrqSafeGuard<'_, T> {
+        let daif = MASK_EXCEPTIONS.load(Ordering::Relaxed).then(|| {
+            let daif = DAIF.get();
+            unsafe { asm!("msr DAIFSet, #0xf", options(nomem, nostack)) };
+            daif
+        });
+        // the spinlock guard is released by hand, along with DAIF
+        core::mem::forget(self.lock.lock());
+        IrqSafeGuard { lock: &self.lock, daif }
+    }
+
+    /// Release the lock taken by a guard which is never dropped, DAIF is then left to the
+    /// `eret` restoring SPSR.
+    ///
+    /// # Safety
+    /// The caller must own the lock.
+    pub unsafe fn force_unlock(&self) {
+        self.lock.force_unlock()
+    }
+}
+
+impl<T> Deref for IrqSafeGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for IrqSafeGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for IrqSafeGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock() };
+        if let Some(daif) = self.daif {
+            DAIF.set(daif);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use linked_list_allocator::LockedHeap;
+use mmio::SysCall;
+
+/// The heap grows by at least a page of the kernel.
+const GROW_SIZE: usize = 0x1_0000;
+
+/// Allocator of the user programs, the heap is grown with `sbrk` whenever it runs out of memory.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static HEAP: UserHeap = UserHeap::empty();
+/// ```
+pub struct UserHeap {
+    heap: LockedHeap,
+}
+
+impl UserHeap {
+    pub const fn empty() -> Self {
+        UserHeap {
+            heap: LockedHeap::empty(),
+        }
+    }
+
+    /// Grow the heap so `layout` fits in it.
+    fn grow(&self, layout: &Layout) -> Result<(), ()> {
+        let size = (layout.size() + layout.align() + GROW_SIZE - 1) & !(GROW_SIZE - 1);
+        let start = SysCall {}.sbrk(size);
+        if start < 0 {
+            return Err(());
+        }
+        let mut heap = self.heap.lock();
+        unsafe {
+            // only the heap moves the end of the heap, the new memory follows the previous one
+            if heap.size() == 0 {
+                heap.init(start as usize, size);
+            } else {
+                heap.extend(size);
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocation = self.heap.lock().allocate_first_fit(layout);
+        match allocation {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => match self.grow(&layout) {
+                Ok(_) => self.heap.lock()
+                    .allocate_first_fit(layout)
+                    .map_or(ptr::null_mut(), |ptr| ptr.as_ptr()),
+                Err(_) => ptr::null_mut(),
+            },
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap.lock().deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+}
@@ -0,0 +1,9 @@
+#![no_std]
+
+//! Support for the user programs, on top of the syscalls of the kernel.
+
+pub mod heap;
+pub mod ps;
+
+pub use heap::UserHeap;
+pub use ps::processes;
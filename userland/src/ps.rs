@@ -0,0 +1,20 @@
+use mmio::syscall::{ProcInfo, SysCall};
+
+/// Statistics of every process, as listed by the kernel when they are read.
+///
+/// ```ignore
+/// for info in processes() {
+///     println!("{} {:?}", info.pid, info.state);
+/// }
+/// ```
+pub fn processes() -> impl Iterator<Item=ProcInfo> {
+    let sys_call = SysCall {};
+    (0..).map_while(move |index| {
+        let mut info = ProcInfo::empty();
+        if sys_call.proc_info(index, &mut info) < 0 {
+            None
+        } else {
+            Some(info)
+        }
+    })
+}